@@ -1,24 +1,41 @@
 //! Application state management
 
+mod audiobooks;
 mod core;
+pub mod cover_art;
+mod fade;
+mod loops;
+mod loudness;
+mod lyrics;
 mod playlist;
 mod playlist_behavior;
+mod podcasts;
+mod trainer;
 pub mod tray;
 pub mod ui;
 
 pub use playlist_behavior::PlaylistBehavior;
 use {
     self::{
+        audiobooks::BookmarkStore,
         core::Core,
+        cover_art::CoverArts,
+        loops::LoopStore,
+        loudness::Loudness,
+        podcasts::Podcasts,
         tray::{AppToTrayMsg, AppTray},
     },
     crate::{
         config::Config,
+        ipc::{
+            SocketPath,
+            property::{self, Property as _},
+        },
         mpv_handler::{ActivePtyInput, MpvHandler},
     },
     anyhow::Context as _,
     egui_sf2g::egui::{self, Context, Event, Key},
-    playlist::Playlist,
+    playlist::{Playlist, archive::Extractor},
     std::{fmt::Display, path::PathBuf, sync::Mutex, time::Instant},
     zbus::names::BusName,
 };
@@ -83,14 +100,42 @@ impl App {
             Some(result) => result.context("Failed to load config")?,
             None => Config::default(),
         };
+        let mut mpv_handler = MpvHandler::new(SocketPath::new(cfg.ipc_socket_path.as_deref()));
+        // The chapters are shown all the time
+        mpv_handler.observe(property::ChapterList::NAME);
+        mpv_handler.observe(property::CurrentChapter::NAME);
         let mut core = Core {
             cfg,
             playlist: Playlist::default(),
             selected_song: 0,
-            mpv_handler: MpvHandler::default(),
+            mpv_handler,
             playlist_behavior: PlaylistBehavior::Continue,
             user_stopped: true,
             song_change: false,
+            fade: None,
+            crossfade_out: None,
+            playback_error: None,
+            consecutive_failures: 0,
+            failed_songs: Vec::new(),
+            resume_pos: None,
+            recovery_attempts: 0,
+            unbound_effects: None,
+            bound_preset: None,
+            loudness: Loudness::load(),
+            loops: LoopStore::load(),
+            trainer: None,
+            podcasts: Podcasts::load(),
+            bookmarks: BookmarkStore::load(),
+            paused_since: None,
+            sleep_until: None,
+            playing_book: None,
+            lyrics: None,
+            embedded_lyrics_checked: false,
+            covers: CoverArts::default(),
+            playing_episode: None,
+            extractor: Extractor::default(),
+            extracting: None,
+            playing_cue: None,
         };
         // Handle path argument for opening a folder (and optionally play a file)
         let mut play_this = None;
@@ -123,7 +168,12 @@ impl App {
             modal: ModalPopup::default(),
         };
         if let Some(this) = play_this {
-            if let Some(pos) = app.core.playlist.iter().position(|item| item.path == this) {
+            if let Some(pos) = app
+                .core
+                .playlist
+                .iter()
+                .position(|item| item.path() == Some(this))
+            {
                 app.focus_and_play(pos);
             }
         }
@@ -134,24 +184,57 @@ impl App {
         if !ctx.wants_keyboard_input() {
             self.handle_egui_input(ctx);
         }
-        self.handle_mpv_events();
         self.core.mpv_handler.update(&mut self.modal);
+        self.handle_mpv_events();
+        self.core.update_fade(&mut self.modal);
+        self.core.update_crossfade(&mut self.modal);
+        self.core.update_trainer(&mut self.modal);
+        self.core.covers.update();
+        self.core.update_extraction(&mut self.modal);
+        self.core.update_audiobook(&mut self.modal);
+        self.core.update_sleep_timer(&mut self.modal);
+        self.core.update_lyrics();
         self.core.handle_mpv_not_active(&mut self.modal);
+        self.core.loudness.update();
+        self.core.update_podcast_progress();
+        if self.core.update_archive_listings() {
+            self.ui.recalc_filt_entries(&self.core);
+        }
+        if self.core.podcasts.update(&self.core.cfg.podcasts) {
+            self.core.set_episodes();
+            self.ui.recalc_filt_entries(&self.core);
+        }
         // Do the ui
         self.ui.update(&mut self.core, ctx, &mut self.modal);
     }
 
     fn handle_mpv_events(&mut self) {
         while let Some(event) = self.core.mpv_handler.poll_event() {
-            self.core.handle_event(event);
+            self.core.handle_event(event, &mut self.modal);
         }
     }
 
     /// Update when in the background (window not open)
     pub fn bg_update(&mut self) {
-        self.handle_mpv_events();
         self.core.mpv_handler.update(&mut self.modal);
+        self.handle_mpv_events();
+        self.core.update_fade(&mut self.modal);
+        self.core.update_crossfade(&mut self.modal);
+        self.core.update_trainer(&mut self.modal);
+        self.core.covers.update();
+        self.core.update_extraction(&mut self.modal);
+        self.core.update_audiobook(&mut self.modal);
+        self.core.update_sleep_timer(&mut self.modal);
         self.core.handle_mpv_not_active(&mut self.modal);
+        self.core.loudness.update();
+        self.core.update_podcast_progress();
+        if self.core.update_archive_listings() {
+            self.ui.recalc_filt_entries(&self.core);
+        }
+        if self.core.podcasts.update(&self.core.cfg.podcasts) {
+            self.core.set_episodes();
+            self.ui.recalc_filt_entries(&self.core);
+        }
     }
 
     /// Update when tray popup is open
@@ -164,8 +247,20 @@ impl App {
 
     pub fn save(&mut self) {
         self.core.save_mpv_values_to_cfg();
+        // The effects of a bound preset shouldn't become the regular effects
+        let bound_effects = self
+            .core
+            .unbound_effects
+            .clone()
+            .map(|rack| std::mem::replace(&mut self.core.cfg.effects, rack));
         let vec = serde_json::to_vec_pretty(&self.core.cfg).unwrap();
+        if let Some(rack) = bound_effects {
+            self.core.cfg.effects = rack;
+        }
         std::fs::write(Config::path(), vec).unwrap();
+        // Listening progress isn't saved as it changes
+        self.core.podcasts.save();
+        self.core.bookmarks.save();
     }
 
     fn handle_egui_input(&mut self, ctx: &Context) {
@@ -209,17 +304,11 @@ impl App {
         });
     }
 
-    pub(crate) fn paused_or_stopped(&self) -> bool {
-        !self.core.mpv_handler.active() || self.core.mpv_handler.paused()
-    }
-
     pub fn currently_playing_name(&self) -> Option<&str> {
         self.core
             .playlist
             .get(self.core.selected_song)?
-            .path
-            .file_name()
-            .and_then(|name| name.to_str())
+            .short_name()
     }
 
     pub(crate) fn update_tooltip(&mut self) {
@@ -230,10 +319,25 @@ impl App {
             return;
         }
         let mut buf = String::new();
+        buf.push_str(self.core.mpv_handler.playback_state().label());
+        buf.push('\n');
         if let Some(currently_playing) = self.currently_playing_name() {
             buf.push_str(currently_playing);
             buf.push('\n');
         }
+        if let Some(title) = self.core.stream_title() {
+            buf.push_str(&title);
+            buf.push('\n');
+        }
+        let (chapters, current) = self.core.mpv_handler.chapters();
+        if let Some(idx) = current {
+            buf.push_str(&format!(
+                "Chapter {}/{}: {}\n",
+                idx + 1,
+                chapters.len(),
+                chapters[idx].display_title(idx)
+            ));
+        }
         if let Some(last) = self.core.mpv_handler.mpv_output().lines().last() {
             buf.push_str(last);
         }
@@ -256,7 +360,11 @@ impl App {
     }
 
     pub(crate) fn update_volume(&mut self) {
-        if let Some(vol) = self.core.mpv_handler.ipc(|b| b.observed.volume) {
+        // The observed volume is transient during a fade
+        if self.core.fading() {
+            return;
+        }
+        if let Some(Some(vol)) = self.core.mpv_handler.ipc(|b| b.volume()) {
             self.core.cfg.volume = vol;
         }
     }
@@ -264,7 +372,7 @@ impl App {
     pub(crate) fn focus_and_play(&mut self, idx: usize) {
         self.core.selected_song = idx;
         self.ui.focus_on = Some(idx);
-        self.core.play_selected_song(&mut self.modal);
+        self.core.switch_to_selected_song(&mut self.modal);
     }
 }
 
@@ -0,0 +1,74 @@
+//! Listening positions of audiobooks, saved per book folder
+
+use {
+    super::playlist::Item,
+    crate::util::json_store::{self, Dir},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    },
+};
+
+const FILE_NAME: &str = "audiobooks.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Bookmark {
+    /// The file of the book being listened to, relative to the music folder
+    pub file: PathBuf,
+    /// The CUE track being listened to, if the file is split into tracks
+    #[serde(default)]
+    pub track: Option<u32>,
+    /// Position in the file, in seconds
+    pub pos: f64,
+}
+
+impl Bookmark {
+    /// A bookmark at the start of `item`, if it's a local song
+    pub fn at_start(item: &Item) -> Option<Self> {
+        let (file, track) = match item {
+            Item::File(path) => (path, None),
+            Item::CueTrack { path, track } => (path, Some(track.number)),
+            _ => return None,
+        };
+        Some(Self {
+            file: file.clone(),
+            track,
+            pos: 0.0,
+        })
+    }
+    /// Whether the bookmark points into `item`
+    pub fn is_in(&self, item: &Item) -> bool {
+        match item {
+            Item::File(path) => *path == self.file && self.track.is_none(),
+            Item::CueTrack { path, track } => {
+                *path == self.file && self.track == Some(track.number)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BookmarkStore {
+    /// Keyed by book folder, relative to the music folder
+    bookmarks: HashMap<PathBuf, Bookmark>,
+}
+
+impl BookmarkStore {
+    pub fn load() -> Self {
+        Self {
+            bookmarks: json_store::load(Dir::Data, FILE_NAME, "audiobook bookmarks"),
+        }
+    }
+    pub fn save(&self) {
+        json_store::save(Dir::Data, FILE_NAME, "audiobook bookmarks", &self.bookmarks);
+    }
+    pub fn get(&self, book: &Path) -> Option<&Bookmark> {
+        self.bookmarks.get(book)
+    }
+    /// Update the bookmark of a book. Not saved until [`Self::save`] is called.
+    pub fn set(&mut self, book: &Path, bookmark: Bookmark) {
+        self.bookmarks.insert(book.to_owned(), bookmark);
+    }
+}
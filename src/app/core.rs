@@ -1,13 +1,31 @@
 use {
-    super::{ModalPopup, PlaylistBehavior, playlist::Playlist},
+    super::{
+        ModalPopup, PlaylistBehavior,
+        audiobooks::{Bookmark, BookmarkStore},
+        cover_art::{CoverArt, CoverArts},
+        fade::{Fade, FadeThen},
+        loops::LoopStore,
+        loudness::{Analysis, Loudness},
+        lyrics::{self, Lyrics},
+        playlist::{Item, Playlist, archive, cue::CueTrack},
+        podcasts::{EpisodeState, Podcasts},
+        trainer::Trainer,
+    },
     crate::{
-        config::{Config, PredicateSliceExt},
-        ipc::Bridge,
+        config::{Config, PredicateSliceExt, ReplayGainMode},
+        effects::{self, EffectsRack},
+        ipc::{EndFileReason, IpcEvent, PlaybackState, property},
         logln,
         mpv_handler::{CustomDemuxer, MpvHandler},
+        time_fmt::FfmpegTimeFmt,
         util::result_ext::ResultModalExt,
     },
-    std::{ffi::OsStr, path::PathBuf},
+    std::{
+        borrow::Cow,
+        ffi::{OsStr, OsString},
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
 };
 
 pub struct Core {
@@ -22,69 +40,601 @@ pub struct Core {
     ///
     /// We can use this to scroll to the changed song in the ui for example.
     pub(super) song_change: bool,
+    /// Volume fade that's currently in progress
+    pub(super) fade: Option<Fade>,
+    /// Fade-out of the previous song while crossfading into the next one
+    pub(super) crossfade_out: Option<Fade>,
+    /// Why the current song failed to play, if it did
+    pub(super) playback_error: Option<String>,
+    /// How many songs failed to play in a row
+    pub(super) consecutive_failures: u32,
+    /// Songs that were skipped because they failed to play
+    pub(crate) failed_songs: Vec<FailedSong>,
+    /// If set, the next song is started from this position (used for crash recovery)
+    pub(super) resume_pos: Option<f64>,
+    /// How many times we tried to recover the current song after a crash
+    pub(super) recovery_attempts: u32,
+    /// The effects that were in use before a preset bound to the current song replaced them.
+    ///
+    /// Restored once a song without a bound preset is played.
+    pub(super) unbound_effects: Option<EffectsRack>,
+    /// Name of the effect preset bound to the current song, which edits of the effects go to
+    pub(crate) bound_preset: Option<String>,
+    pub(crate) loudness: Loudness,
+    /// Saved A-B loop sections
+    pub(crate) loops: LoopStore,
+    pub(crate) trainer: Option<Trainer>,
+    pub(crate) podcasts: Podcasts,
+    pub(crate) bookmarks: BookmarkStore,
+    /// When playback was paused, for rewinding audiobooks after a long pause
+    pub(super) paused_since: Option<Instant>,
+    /// Playback is stopped at this point
+    pub(crate) sleep_until: Option<Instant>,
+    /// The audiobook being played, and where in it playback is
+    pub(super) playing_book: Option<(PathBuf, Bookmark)>,
+    /// Lyrics of the current song
+    pub(crate) lyrics: Option<Lyrics>,
+    /// Whether the current song was checked for embedded lyrics (or doesn't need to be)
+    pub(super) embedded_lyrics_checked: bool,
+    pub(crate) covers: CoverArts,
+    /// `(feed URL, guid)` of the podcast episode being played.
+    ///
+    /// Its index in the playlist can change while it plays, or it can even be removed.
+    pub(super) playing_episode: Option<(String, String)>,
+    pub(crate) extractor: archive::Extractor,
+    /// The CUE track being played. mpv plays its file, limited to the track with `--start`
+    /// and `--end`, so it only knows the position and duration in the whole file.
+    pub(super) playing_cue: Option<CueTrack>,
+    /// Fade-in of the selected song, which is started once it's extracted from its archive
+    pub(super) extracting: Option<u32>,
+}
+
+/// Change the playlist with `f`, keeping the selected song selected even if its index changes
+fn keep_selection<R>(
+    playlist: &mut Playlist,
+    selected_song: &mut usize,
+    f: impl FnOnce(&mut Playlist) -> R,
+) -> R {
+    let selected = playlist.get(*selected_song).cloned();
+    let result = f(playlist);
+    if let Some(selected) = selected
+        && let Some(idx) = playlist.iter().position(|item| item.is_same(&selected))
+    {
+        *selected_song = idx;
+    }
+    result
+}
+
+pub struct FailedSong {
+    pub path: PathBuf,
+    pub reason: String,
 }
 
 impl Core {
     pub(crate) fn read_songs(&mut self) {
         self.playlist.read_songs(&self.cfg);
+        self.set_episodes();
+        if self.cfg.replaygain.analyze_untagged {
+            self.analyze_loudness();
+        }
+    }
+
+    /// Replace the podcast episodes in the playlist with the current ones
+    pub(crate) fn set_episodes(&mut self) {
+        let feeds = &self.podcasts.feeds;
+        keep_selection(&mut self.playlist, &mut self.selected_song, |playlist| {
+            playlist.set_episodes(feeds)
+        });
+    }
+
+    /// Replace the stations in the playlist with the configured ones
+    pub(crate) fn set_stations(&mut self) {
+        let stations = &self.cfg.stations;
+        keep_selection(&mut self.playlist, &mut self.selected_song, |playlist| {
+            playlist.set_stations(stations)
+        });
+    }
+
+    /// Add the songs of archives that were listed in the background.
+    ///
+    /// Returns whether the playlist changed.
+    pub(super) fn update_archive_listings(&mut self) -> bool {
+        keep_selection(
+            &mut self.playlist,
+            &mut self.selected_song,
+            Playlist::collect_archive_listings,
+        )
+    }
+
+    /// Play the selected song once it's extracted from its archive
+    pub(super) fn update_extraction(&mut self, modal: &mut ModalPopup) {
+        if self.extractor.update()
+            && let Some(fade_in_ms) = self.extracting.take()
+        {
+            self.play_selected_song_inner(modal, fade_in_ms);
+        }
+    }
+
+    /// Full path of the selected song
+    pub(crate) fn selected_song_path(&self) -> Option<PathBuf> {
+        let item = self.playlist.get(self.selected_song)?;
+        Some(self.cfg.music_folder.as_ref()?.join(item.virtual_path()?))
+    }
+
+    /// Cover art of the selected song, once it has been looked up
+    pub(crate) fn cover_art(&self) -> Option<&CoverArt> {
+        self.covers.get(&self.selected_song_path()?)
+    }
+
+    /// Title of what's currently on, if a stream is playing
+    pub(crate) fn stream_title(&self) -> Option<String> {
+        if !self.playlist.get(self.selected_song)?.is_stream() {
+            return None;
+        }
+        self.mpv_handler.media_title()
+    }
+
+    /// The audiobook folder the song at `idx` belongs to, if any
+    pub(crate) fn book_of(&self, idx: usize) -> Option<&Path> {
+        let (Item::File(path) | Item::CueTrack { path, .. }) = self.playlist.get(idx)? else {
+            return None;
+        };
+        self.cfg
+            .audiobook
+            .folders
+            .iter()
+            .find(|folder| path.starts_with(folder))
+            .map(PathBuf::as_path)
+    }
+
+    pub(crate) fn current_book(&self) -> Option<&Path> {
+        self.book_of(self.selected_song)
+    }
+
+    /// Index of the song a bookmark points into
+    fn bookmark_index(&self, bookmark: &Bookmark) -> Option<usize> {
+        let idx = self.playlist.iter().position(|item| bookmark.is_in(item));
+        if idx.is_none() {
+            logln!("Bookmarked file {} is gone", bookmark.file.display());
+        }
+        idx
+    }
+
+    /// Jump to where the current audiobook was left off
+    pub(crate) fn continue_book(&mut self, modal: &mut ModalPopup) {
+        let Some(idx) = self
+            .current_book()
+            .and_then(|book| self.bookmarks.get(book))
+            .and_then(|bookmark| self.bookmark_index(bookmark))
+        else {
+            return;
+        };
+        self.selected_song = idx;
+        self.song_change = true;
+        self.switch_to_selected_song(modal);
+    }
+
+    /// Keep track of the audiobook being played, before the selected song starts playing.
+    ///
+    /// Entering a book arms the sleep timer, and starting it from its first file continues
+    /// where it was left off instead. Leaving the books turns the sleep timer off.
+    fn enter_or_leave_book(&mut self) {
+        let Some(book) = self.current_book().map(Path::to_owned) else {
+            if self.playing_book.take().is_some() {
+                self.sleep_until = None;
+            }
+            return;
+        };
+        let entering = self
+            .playing_book
+            .as_ref()
+            .is_none_or(|(playing, _)| *playing != book);
+        if entering {
+            // Only armed when entering, so turning it off lasts until the book is left
+            let minutes = self.cfg.audiobook.sleep_timer;
+            if minutes != 0 {
+                logln!("Sleep timer set to {minutes} minutes");
+                self.sleep_until =
+                    Some(Instant::now() + Duration::from_secs(u64::from(minutes) * 60));
+            }
+            // Anything but the first file was picked on purpose
+            let first_file =
+                (0..self.playlist.len()).find(|&idx| self.book_of(idx) == Some(book.as_path()));
+            if first_file == Some(self.selected_song)
+                && let Some(idx) = self
+                    .bookmarks
+                    .get(&book)
+                    .and_then(|bookmark| self.bookmark_index(bookmark))
+                && idx != self.selected_song
+            {
+                logln!("Continuing {} where it was left off", book.display());
+                self.selected_song = idx;
+                self.song_change = true;
+            }
+        }
+        self.playing_book = self
+            .playlist
+            .get(self.selected_song)
+            .and_then(Bookmark::at_start)
+            .map(|bookmark| (book, bookmark));
+    }
+
+    /// Keep the bookmark of the playing audiobook up to date, and rewind after long pauses
+    pub(super) fn update_audiobook(&mut self, modal: &mut ModalPopup) {
+        if self.playing_book.is_none() {
+            self.paused_since = None;
+            return;
+        }
+        match self.mpv_handler.playback_state() {
+            PlaybackState::Paused => {
+                self.paused_since.get_or_insert_with(Instant::now);
+            }
+            PlaybackState::Playing => {
+                let rewind_after =
+                    Duration::from_secs(u64::from(self.cfg.audiobook.rewind_after) * 60);
+                if let Some(since) = self.paused_since.take()
+                    && since.elapsed() >= rewind_after
+                {
+                    let rewind_by = self.cfg.audiobook.rewind_by;
+                    logln!("Rewinding {rewind_by} seconds after a long pause");
+                    self.seek_relative(-rewind_by)
+                        .err_popup("Seek error", modal);
+                }
+            }
+            _ => {}
+        }
+        // The bookmark is only moved once mpv has started playing (at the bookmark)
+        if let Some((book, bookmark)) = &mut self.playing_book
+            && let Some(info) = self.mpv_handler.time_info()
+            && info.pos > 0.0
+        {
+            bookmark.pos = info.pos;
+            self.bookmarks.set(book, bookmark.clone());
+        }
+    }
+
+    /// Use the embedded lyrics once mpv has read the tags, unless there is a lyrics file
+    pub(super) fn update_lyrics(&mut self) {
+        if self.embedded_lyrics_checked {
+            return;
+        }
+        let Some(embedded) = self.mpv_handler.embedded_lyrics() else {
+            return;
+        };
+        self.embedded_lyrics_checked = true;
+        if let Some(text) = embedded {
+            self.lyrics = Some(lyrics::parse(&text, lyrics::Source::Embedded));
+        }
+    }
+
+    /// Stop playback when the sleep timer runs out
+    pub(super) fn update_sleep_timer(&mut self, modal: &mut ModalPopup) {
+        if self
+            .sleep_until
+            .is_some_and(|until| Instant::now() >= until)
+        {
+            self.sleep_until = None;
+            if self.mpv_handler.active() {
+                logln!("Sleep timer ran out, stopping playback");
+                self.stop_music(modal);
+            }
+        }
+    }
+
+    /// Remember how far the playing podcast episode was listened to
+    pub(super) fn update_podcast_progress(&mut self) {
+        let Some((feed_url, guid)) = &self.playing_episode else {
+            return;
+        };
+        let Some(info) = self.mpv_handler.time_info() else {
+            return;
+        };
+        let played = self
+            .podcasts
+            .episode(feed_url, guid)
+            .is_some_and(|ep| ep.state == EpisodeState::Played);
+        if info.pos > 0.0 && !played {
+            self.podcasts
+                .set_state(feed_url, guid, EpisodeState::InProgress(info.pos));
+        }
+    }
+
+    /// Queue the songs of the playlist for loudness analysis
+    pub(crate) fn analyze_loudness(&mut self) {
+        let Some(folder) = &self.cfg.music_folder else {
+            return;
+        };
+        let mut paths: Vec<_> = self
+            .playlist
+            .iter()
+            .filter_map(|item| match item {
+                Item::File(path) | Item::CueTrack { path, .. } => Some(folder.join(path)),
+                // ffmpeg can't read from inside archives
+                Item::ArchiveEntry { .. } | Item::Url { .. } | Item::Episode { .. } => None,
+            })
+            .collect();
+        // The tracks of a CUE sheet share the same file
+        paths.dedup();
+        self.loudness.analyze(paths);
     }
 
     pub(crate) fn play_selected_song(&mut self, modal: &mut ModalPopup) {
         self.save_mpv_values_to_cfg();
+        self.play_selected_song_inner(modal, 0);
+    }
+
+    /// Manually switch to the selected song, fading out the current one if configured
+    pub(crate) fn switch_to_selected_song(&mut self, modal: &mut ModalPopup) {
+        // Already fading out, the new selection will be played when it's done
+        if let Some(Fade {
+            then: FadeThen::PlaySelected,
+            ..
+        }) = &self.fade
+        {
+            return;
+        }
+        self.save_mpv_values_to_cfg();
+        let fade_ms = self.cfg.fade.track_change;
+        self.finish_fade(modal);
+        if fade_ms != 0 && self.mpv_handler.active() && !self.mpv_handler.paused() {
+            self.begin_fade_out(fade_ms, FadeThen::PlaySelected);
+        } else {
+            self.play_selected_song_inner(modal, fade_ms);
+        }
+    }
+
+    /// Plays the selected song. If `fade_in_ms` is nonzero, the song starts silent and fades in.
+    ///
+    /// The caller is responsible for saving the mpv values to the config beforehand.
+    fn play_selected_song_inner(&mut self, modal: &mut ModalPopup, fade_in_ms: u32) {
+        self.fade = None;
         self.user_stopped = false;
+        self.extracting = None;
+        self.enter_or_leave_book();
         let selection = self.selected_song;
         let Some(sel_item) = &self.playlist.get(selection) else {
             logln!("play_selected_song: Dangling index: {selection}");
             return;
         };
-        let path: PathBuf = match &self.cfg.music_folder {
-            Some(folder) => folder.join(&sel_item.path),
-            None => {
-                logln!("Can't play song, there is no music folder");
-                return;
+        let rel_path = sel_item.virtual_path().map(Cow::into_owned);
+        let cue = match sel_item {
+            Item::CueTrack { track, .. } => Some(track.clone()),
+            _ => None,
+        };
+        self.playing_cue = cue.clone();
+        // Continue audiobooks where they were left off, with a bit of context
+        let book_pos = self
+            .book_of(selection)
+            .and_then(|book| self.bookmarks.get(book))
+            .filter(|bookmark| bookmark.is_in(sel_item))
+            .map(|bookmark| (bookmark.pos - self.cfg.audiobook.rewind_by).max(0.0));
+        self.paused_since = None;
+        self.playing_episode = match sel_item {
+            Item::Episode { feed_url, guid, .. } => Some((feed_url.clone(), guid.clone())),
+            _ => None,
+        };
+        // Continue podcast episodes where they were left off
+        let episode_pos = match sel_item {
+            Item::Episode { feed_url, guid, .. } => {
+                match self.podcasts.episode(feed_url, guid).map(|ep| ep.state) {
+                    Some(EpisodeState::InProgress(pos)) => Some(pos),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let mut archive = None;
+        // Full path of local songs, for demuxers and loudness
+        let mut path = None;
+        let mpv_target: OsString = match sel_item {
+            Item::Url { url, .. } | Item::Episode { location: url, .. } => url.into(),
+            _ => {
+                let (Some(folder), Some(rel_path)) = (&self.cfg.music_folder, &rel_path) else {
+                    logln!("Can't play song, there is no music folder");
+                    return;
+                };
+                let full_path = folder.join(rel_path);
+                path = Some(full_path.clone());
+                match sel_item {
+                    // Files inside archives are played with mpv's archive protocol
+                    Item::ArchiveEntry {
+                        archive: archive_path,
+                        entry,
+                    } => {
+                        let archive_path = folder.join(archive_path);
+                        let url = archive::mpv_url(&archive_path, entry);
+                        archive = Some((archive_path, entry.clone()));
+                        url.into()
+                    }
+                    _ => full_path.into(),
+                }
             }
         };
-        let vol_arg = format!("--volume={}", self.cfg.volume);
+        let demuxer_entry = path.as_ref().and_then(|path| {
+            self.cfg
+                .custom_demuxers
+                .iter()
+                .find(|en| en.predicates.find_predicate_match(path))
+        });
+        // Demuxer commands need a real file, so songs in archives are extracted first
+        let mut extracted = None;
+        if demuxer_entry.is_some()
+            && let Some((archive_path, entry)) = &archive
+        {
+            match self.extractor.get_or_request(archive_path, entry) {
+                Some(Ok(file)) => extracted = Some(file),
+                Some(Err(e)) => {
+                    self.playback_error = Some(format!("Failed to extract from archive: {e:#}"));
+                    return;
+                }
+                None => {
+                    logln!("Extracting {} from archive", entry.display());
+                    self.mpv_handler.stop_music();
+                    self.extracting = Some(fade_in_ms);
+                    return;
+                }
+            }
+        }
+        let start_vol = if fade_in_ms == 0 { self.cfg.volume } else { 0 };
+        let vol_arg = format!("--volume={start_vol}");
         let speed_arg = format!("--speed={}", self.cfg.speed);
-        let mut mpv_args = vec![
-            path.as_ref(),
-            "--input-ipc-server=/tmp/mpvfrog.sock".as_ref(),
-            vol_arg.as_ref(),
-            speed_arg.as_ref(),
-        ];
+        let mut mpv_args = vec![mpv_target.as_ref(), vol_arg.as_ref(), speed_arg.as_ref()];
         if !self.cfg.video {
             mpv_args.push("--no-video".as_ref());
         }
-        let demuxer = match self
-            .cfg
-            .custom_demuxers
-            .iter()
-            .find(|en| en.predicates.find_predicate_match(&path))
+        let resume_pos = self.resume_pos.take();
+        if resume_pos.is_none() {
+            self.recovery_attempts = 0;
+        }
+        let start_arg;
+        if let Some(pos) = resume_pos
+            .or(book_pos)
+            .or(cue.as_ref().map(|track| track.start))
+            .or(episode_pos)
         {
-            Some(en) => {
+            start_arg = format!("--start={pos}");
+            mpv_args.push(start_arg.as_ref());
+        }
+        // mpv ends the file at the end of the cue track, so the next song is played
+        let end_arg;
+        if let Some(end) = cue.as_ref().and_then(|track| track.end) {
+            end_arg = format!("--end={end}");
+            mpv_args.push(end_arg.as_ref());
+        }
+        let demuxer_name = demuxer_entry.map(|en| en.name.clone());
+        let demuxer = match demuxer_entry.zip(path.as_ref()) {
+            Some((en, path)) => {
                 mpv_args.remove(0);
                 mpv_args.extend(en.extra_mpv_args.iter().map(<_ as AsRef<OsStr>>::as_ref));
+                let demux_path = extracted.as_ref().unwrap_or(path);
                 Some(CustomDemuxer::from_config_cmd(
                     &en.reader_cmd,
-                    path.as_ref(),
+                    demux_path.as_ref(),
                 ))
             }
             None => None,
         };
-        crate::app::LOG.lock().unwrap().clear();
+        // Keep the log when recovering, so it's visible what happened
+        if resume_pos.is_none() {
+            crate::app::LOG.lock().unwrap().clear();
+        }
+        self.select_effects(rel_path.as_deref(), demuxer_name.as_deref());
+        let mut extra_args = Vec::new();
+        extra_args.push(format!("--af={}", self.audio_filters()));
+        if let Some(path) = &path {
+            extra_args.extend(self.replaygain_args(path));
+        }
+        mpv_args.extend(extra_args.iter().map(<_ as AsRef<OsStr>>::as_ref));
         logln!("Mpv args: {mpv_args:?}");
+        self.lyrics = path.as_deref().and_then(Lyrics::load_sidecar);
+        self.embedded_lyrics_checked = self.lyrics.is_some();
+        // ffmpeg can't read from inside archives
+        if let Some(path) = &path
+            && archive.is_none()
+        {
+            self.covers.request(path);
+        }
         if let Err(e) = self.mpv_handler.play_music("mpv", mpv_args, demuxer) {
-            modal.error("Play error", e);
-            self.playlist_behavior = PlaylistBehavior::Stop;
+            self.playback_error = Some(format!("{e:#}"));
+            return;
+        }
+        if fade_in_ms != 0 {
+            self.fade = Some(Fade::new(
+                0.0,
+                self.cfg.volume.into(),
+                fade_in_ms,
+                self.cfg.volume,
+                FadeThen::Nothing,
+            ));
+        }
+    }
+    /// Switch to the effect preset bound to the song, if there is one
+    fn select_effects(&mut self, rel_path: Option<&Path>, demuxer_name: Option<&str>) {
+        let bound = self.cfg.effect_presets.iter().find(|preset| {
+            preset
+                .folders
+                .iter()
+                .any(|folder| rel_path.is_some_and(|path| path.starts_with(folder)))
+                || demuxer_name.is_some_and(|name| preset.demuxers.iter().any(|d| d == name))
+        });
+        match bound {
+            Some(preset) => {
+                logln!("Using effect preset `{}`", preset.name);
+                let rack = preset.rack.clone();
+                let prev = std::mem::replace(&mut self.cfg.effects, rack);
+                self.unbound_effects.get_or_insert(prev);
+                self.bound_preset = Some(preset.name.clone());
+            }
+            None => {
+                if let Some(rack) = self.unbound_effects.take() {
+                    self.cfg.effects = rack;
+                }
+                self.bound_preset = None;
+            }
         }
     }
+    /// Write edits of the effects back to the preset bound to the current song, if any.
+    ///
+    /// Otherwise they would be lost once a song without a bound preset is played.
+    pub(crate) fn store_bound_effects(&mut self) {
+        if let Some(name) = &self.bound_preset
+            && let Some(preset) = self
+                .cfg
+                .effect_presets
+                .iter_mut()
+                .find(|preset| preset.name == *name)
+        {
+            preset.rack = self.cfg.effects.clone();
+        }
+    }
+
+    fn replaygain_args(&self, path: &Path) -> Vec<String> {
+        let rg = &self.cfg.replaygain;
+        if rg.mode == ReplayGainMode::Off {
+            return Vec::new();
+        }
+        let mut args = vec![
+            format!("--replaygain={}", rg.mode.mpv_value()),
+            format!("--replaygain-preamp={}", rg.preamp),
+            format!(
+                "--replaygain-clip={}",
+                if rg.clip_prevention { "yes" } else { "no" }
+            ),
+        ];
+        // mpv only uses the fallback for files without tags, and ignores the preamp for it
+        if let Some(Analysis::Loudness(lufs)) = self.loudness.get(path) {
+            let gain = rg.target_loudness - lufs + rg.preamp;
+            args.push(format!("--replaygain-fallback={gain:.2}"));
+        }
+        args
+    }
+
+    /// The value for mpv's `af` property, based on the current settings
+    pub(crate) fn audio_filters(&self) -> String {
+        let mut af = effects::tempo_filter(self.cfg.tempo_engine, self.cfg.pitch);
+        let effects = self.cfg.effects.af();
+        if !effects.is_empty() {
+            af.push(',');
+            af.push_str(&effects);
+        }
+        af
+    }
+
+    /// Apply changes of the audio filter settings to the running mpv instance
+    pub(crate) fn apply_audio_filters(&mut self, modal: &mut ModalPopup) {
+        let af = self.audio_filters();
+        self.mpv_handler
+            .ipc(|b| b.set_audio_filters(af))
+            .err_popup("Failed to set audio filters", modal);
+    }
+
     pub fn play_prev(&mut self, modal: &mut ModalPopup) {
         if self.selected_song == 0 {
             self.selected_song = self.playlist.len() - 1;
         } else {
             self.selected_song -= 1;
         }
-        self.play_selected_song(modal);
+        self.switch_to_selected_song(modal);
         self.song_change = true;
     }
 
@@ -93,70 +643,470 @@ impl Core {
         if self.selected_song >= self.playlist.len() {
             self.selected_song = 0;
         }
-        self.play_selected_song(modal);
+        self.switch_to_selected_song(modal);
         self.song_change = true;
     }
 
-    pub fn stop_music(&mut self) {
+    pub fn stop_music(&mut self, modal: &mut ModalPopup) {
         self.save_mpv_values_to_cfg();
-        self.mpv_handler.stop_music();
+        let fade_ms = self.cfg.fade.stop;
+        self.finish_fade(modal);
         self.user_stopped = true;
+        self.extracting = None;
+        self.crossfade_out = None;
+        self.mpv_handler.stop_outgoing();
+        if fade_ms != 0 && self.mpv_handler.active() && !self.mpv_handler.paused() {
+            self.begin_fade_out(fade_ms, FadeThen::Stop);
+            return;
+        }
+        self.mpv_handler.stop_music();
     }
 
     pub(super) fn save_mpv_values_to_cfg(&mut self) {
         self.mpv_handler.ipc(|b| {
-            self.cfg.volume = b.observed.volume;
-            self.cfg.speed = b.observed.speed;
+            if let Some(fade) = &self.fade {
+                self.cfg.volume = fade.user_volume;
+            } else if let Some(vol) = b.volume() {
+                self.cfg.volume = vol;
+            }
+            // The trainer changes the speed temporarily
+            if let Some(trainer) = &self.trainer {
+                self.cfg.speed = trainer.prev_speed;
+            } else if let Some(speed) = b.observed.get::<property::Speed>() {
+                self.cfg.speed = speed;
+            }
         });
     }
 
     /// Plays the selected song, or toggles the pause state if already playing
     pub fn play_or_toggle_pause(&mut self, modal: &mut ModalPopup) {
         if self.mpv_handler.active() {
-            self.mpv_handler
-                .ipc(Bridge::toggle_pause)
-                .err_popup("Play error", modal);
+            self.toggle_pause(modal);
         } else {
             self.play_selected_song(modal);
         }
     }
 
+    /// Toggles the pause state, fading out/in if configured
+    pub fn toggle_pause(&mut self, modal: &mut ModalPopup) {
+        let fade_ms = self.cfg.fade.pause_resume;
+        self.finish_fade(modal);
+        if fade_ms == 0 {
+            self.mpv_handler
+                .ipc(|b| b.toggle_pause())
+                .err_popup("Toggle pause error", modal);
+            return;
+        }
+        if self.mpv_handler.paused() {
+            let Some(Some(user_volume)) = self.mpv_handler.ipc(|b| b.volume()) else {
+                return;
+            };
+            self.mpv_handler
+                .ipc(|b| {
+                    b.set_volume_f64(0.0)?;
+                    b.set_pause(false)
+                })
+                .err_popup("Toggle pause error", modal);
+            self.fade = Some(Fade::new(
+                0.0,
+                user_volume.into(),
+                fade_ms,
+                user_volume,
+                FadeThen::Nothing,
+            ));
+        } else {
+            self.begin_fade_out(fade_ms, FadeThen::Pause);
+        }
+    }
+
+    fn begin_fade_out(&mut self, fade_ms: u32, then: FadeThen) {
+        let Some(Some(user_volume)) = self.mpv_handler.ipc(|b| b.volume()) else {
+            return;
+        };
+        self.fade = Some(Fade::new(
+            user_volume.into(),
+            0.0,
+            fade_ms,
+            user_volume,
+            then,
+        ));
+    }
+
+    /// Whether a volume fade (or crossfade) is in progress
+    pub fn fading(&self) -> bool {
+        self.fade.is_some() || self.crossfade_out.is_some()
+    }
+
+    /// Advance the current fade, and carry out its follow-up action once it's finished
+    pub(super) fn update_fade(&mut self, modal: &mut ModalPopup) {
+        let Some(fade) = &self.fade else { return };
+        if fade.finished() || !self.mpv_handler.active() {
+            self.finish_fade(modal);
+            return;
+        }
+        let vol = fade.volume();
+        if let Some(Err(e)) = self.mpv_handler.ipc(|b| b.set_volume_f64(vol)) {
+            logln!("Failed to set fade volume: {e}");
+        }
+    }
+
+    /// Immediately complete the fade in progress (if any)
+    fn finish_fade(&mut self, modal: &mut ModalPopup) {
+        let Some(fade) = self.fade.take() else { return };
+        self.cfg.volume = fade.user_volume;
+        match fade.then {
+            FadeThen::Nothing => {
+                self.mpv_handler
+                    .ipc(|b| b.set_volume(fade.user_volume))
+                    .err_popup("Volume change error", modal);
+            }
+            FadeThen::Pause => {
+                self.mpv_handler
+                    .ipc(|b| {
+                        b.set_pause(true)?;
+                        b.set_volume(fade.user_volume)
+                    })
+                    .err_popup("Toggle pause error", modal);
+            }
+            FadeThen::Stop => {
+                self.mpv_handler.stop_music();
+            }
+            FadeThen::PlaySelected => {
+                self.play_selected_song_inner(modal, self.cfg.fade.track_change);
+            }
+        }
+    }
+
     pub(super) fn handle_mpv_not_active(&mut self, modal: &mut ModalPopup) {
-        if self.user_stopped {
+        if self.user_stopped || self.extracting.is_some() {
             return;
         }
         if !self.mpv_handler.active() {
-            match self.playlist_behavior {
-                PlaylistBehavior::Stop => return,
-                PlaylistBehavior::Continue => {
-                    if self.selected_song + 1 < self.playlist.len() {
-                        self.selected_song += 1;
-                    } else {
+            let abnormal_exit = self.mpv_handler.take_abnormal_exit();
+            if let Some(reason) = &abnormal_exit
+                && self.try_recover(reason, modal)
+            {
+                self.playback_error = None;
+                return;
+            }
+            let failed = match self.playback_error.take().or(abnormal_exit) {
+                Some(reason) => {
+                    if !self.register_failure(reason, modal) {
                         return;
                     }
+                    true
                 }
-                PlaylistBehavior::RepeatOne => {}
-                PlaylistBehavior::RepeatPlaylist => {
-                    self.selected_song += 1;
-                    if self.selected_song >= self.playlist.len() {
-                        self.selected_song = 0;
-                    }
+                None => {
+                    self.consecutive_failures = 0;
+                    false
                 }
+            };
+            let Some(next) = self.next_song_index() else {
+                return;
+            };
+            // Retrying the same unplayable file over and over is pointless
+            if failed && next == self.selected_song {
+                self.user_stopped = true;
+                return;
             }
+            self.selected_song = next;
             // If we reached this point, we can take this as the song having been changed
             self.song_change = true;
             self.play_selected_song(modal);
+            // Log after playing, because starting playback clears the log
+            if failed && let Some(failed) = self.failed_songs.last() {
+                logln!(
+                    "Skipped unplayable file {}: {}",
+                    failed.path.display(),
+                    failed.reason
+                );
+            }
+        }
+    }
+
+    /// Relaunch the selected song at the last known position, if mpv (or the demuxer)
+    /// crashed in the middle of it.
+    ///
+    /// Returns whether a recovery was attempted.
+    fn try_recover(&mut self, reason: &str, modal: &mut ModalPopup) -> bool {
+        let pos = self.mpv_handler.last_time_pos();
+        // If it crashed before playing anything, it's probably not going to work on retry
+        if !self.cfg.auto_recover || pos <= 0.0 {
+            return false;
+        }
+        if self.recovery_attempts >= self.cfg.max_recovery_attempts {
+            logln!(
+                "{reason} at {}. Giving up after {} recovery attempts.",
+                FfmpegTimeFmt(pos),
+                self.recovery_attempts
+            );
+            return false;
+        }
+        self.recovery_attempts += 1;
+        logln!(
+            "{reason} at {}. Recovering (attempt {}/{})",
+            FfmpegTimeFmt(pos),
+            self.recovery_attempts,
+            self.cfg.max_recovery_attempts
+        );
+        self.resume_pos = Some(pos);
+        self.play_selected_song(modal);
+        true
+    }
+
+    /// Records that the selected song failed to play.
+    ///
+    /// Returns whether playback should continue with the next song.
+    fn register_failure(&mut self, reason: String, modal: &mut ModalPopup) -> bool {
+        let path = self
+            .playlist
+            .get(self.selected_song)
+            .map(|item| match item.virtual_path() {
+                Some(path) => path.into_owned(),
+                None => PathBuf::from(item.display_name().as_ref()),
+            })
+            .unwrap_or_default();
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= self.cfg.max_consecutive_failures {
+            modal.warn(
+                "Playback stopped",
+                format!(
+                    "{} files in a row failed to play.\nLast error ({}): {reason}",
+                    self.consecutive_failures,
+                    path.display()
+                ),
+            );
+            self.failed_songs.push(FailedSong { path, reason });
+            self.consecutive_failures = 0;
+            self.user_stopped = true;
+            return false;
+        }
+        self.failed_songs.push(FailedSong { path, reason });
+        true
+    }
+
+    /// The song that should follow the current one, according to the playlist behavior
+    fn next_song_index(&self) -> Option<usize> {
+        // Audiobooks continue with their next file, and stop at their end
+        if let Some(book) = self.current_book() {
+            let next = self.selected_song + 1;
+            return (self.book_of(next) == Some(book)).then_some(next);
         }
+        match self.playlist_behavior {
+            PlaylistBehavior::Stop => None,
+            PlaylistBehavior::Continue => {
+                let next = self.selected_song + 1;
+                (next < self.playlist.len()).then_some(next)
+            }
+            PlaylistBehavior::RepeatOne => Some(self.selected_song),
+            PlaylistBehavior::RepeatPlaylist => {
+                let next = self.selected_song + 1;
+                Some(if next >= self.playlist.len() { 0 } else { next })
+            }
+        }
+    }
+
+    /// Advance the fade-out of the previous song, and start crossfading into the next song
+    /// when the current one is nearing its end.
+    pub(super) fn update_crossfade(&mut self, modal: &mut ModalPopup) {
+        if let Some(fade) = &self.crossfade_out {
+            if fade.finished() {
+                self.mpv_handler.stop_outgoing();
+                self.crossfade_out = None;
+            } else {
+                let vol = fade.volume();
+                if let Some(Err(e)) = self.mpv_handler.outgoing_ipc(|b| b.set_volume_f64(vol)) {
+                    logln!("Failed to set crossfade volume: {e}");
+                }
+            }
+        }
+        let crossfade_ms = self.cfg.fade.crossfade;
+        if crossfade_ms == 0
+            || self.user_stopped
+            || self.fade.is_some()
+            || self.crossfade_out.is_some()
+        {
+            return;
+        }
+        let Some(Some((time_pos, duration, looping, volume))) = self.mpv_handler.ipc(|b| {
+            let obs = &b.observed;
+            let looping = b.loop_file()
+                || obs.get::<property::AbLoopA>().flatten().is_some()
+                || obs.get::<property::AbLoopB>().flatten().is_some();
+            let duration = obs.get::<property::Duration>()?;
+            let time_pos = obs.get::<property::TimePos>()?;
+            Some((time_pos, duration, looping, b.volume()?))
+        }) else {
+            return;
+        };
+        let (start, end) = self.song_bounds(duration);
+        let remaining = end - time_pos;
+        let duration = end - start;
+        // Don't let the crossfade take up more than half of a (short) song
+        let crossfade_secs = (f64::from(crossfade_ms) / 1000.0).min(duration / 2.0);
+        if looping || self.mpv_handler.paused() || duration <= 0.0 || remaining > crossfade_secs {
+            return;
+        }
+        // Repeating the same song doesn't get crossfaded
+        let Some(next) = self
+            .next_song_index()
+            .filter(|&idx| idx != self.selected_song)
+        else {
+            return;
+        };
+        self.save_mpv_values_to_cfg();
+        self.mpv_handler.begin_crossfade_out();
+        let fade_ms = (crossfade_secs * 1000.0) as u32;
+        self.crossfade_out = Some(Fade::new(
+            volume.into(),
+            0.0,
+            fade_ms,
+            volume,
+            FadeThen::Nothing,
+        ));
+        self.selected_song = next;
+        self.song_change = true;
+        self.play_selected_song_inner(modal, fade_ms);
+    }
+
+    /// Where the playing song starts and ends in its file (of `duration` seconds).
+    ///
+    /// That's the whole file, except for CUE tracks.
+    pub(crate) fn song_bounds(&self, duration: f64) -> (f64, f64) {
+        match &self.playing_cue {
+            Some(track) => (track.start, track.end.unwrap_or(duration)),
+            None => (0.0, duration),
+        }
+    }
+
+    /// Start the practice trainer on the current A-B loop
+    pub(crate) fn start_trainer(&mut self, modal: &mut ModalPopup) {
+        let Some((Some(a), Some(_))) = self.mpv_handler.ab_loop() else {
+            modal.warn("Practice trainer", "Set an A-B loop first");
+            return;
+        };
+        self.save_mpv_values_to_cfg();
+        let trainer = Trainer::new(self.cfg.trainer.clone(), self.cfg.speed);
+        let speed = trainer.speed;
+        self.trainer = Some(trainer);
+        self.mpv_handler
+            .ipc(|b| {
+                b.set_speed(speed)?;
+                b.seek(a)
+            })
+            .err_popup("Failed to start practice trainer", modal);
+    }
+
+    pub(crate) fn stop_trainer(&mut self, modal: &mut ModalPopup) {
+        let Some(trainer) = self.trainer.take() else {
+            return;
+        };
+        self.mpv_handler
+            .ipc(|b| {
+                if trainer.count_in_until.is_some() {
+                    b.set_pause(false)?;
+                }
+                b.set_speed(trainer.prev_speed)
+            })
+            .err_popup("Failed to stop practice trainer", modal);
+    }
+
+    /// Count repetitions of the A-B loop, and speed up or pause between them as configured
+    pub(super) fn update_trainer(&mut self, modal: &mut ModalPopup) {
+        let Some(trainer) = &mut self.trainer else {
+            return;
+        };
+        if !self.mpv_handler.active() {
+            logln!("Practice trainer stopped: playback ended");
+            self.trainer = None;
+            return;
+        }
+        if let Some(until) = trainer.count_in_until {
+            if Instant::now() >= until {
+                trainer.count_in_until = None;
+                self.mpv_handler
+                    .ipc(|b| b.set_pause(false))
+                    .err_popup("Failed to resume after count-in", modal);
+            }
+            return;
+        }
+        let (Some((Some(a), Some(b))), Some(info)) =
+            (self.mpv_handler.ab_loop(), self.mpv_handler.time_info())
+        else {
+            logln!("Practice trainer stopped: the A-B loop was unset");
+            self.stop_trainer(modal);
+            return;
+        };
+        let speed_before = trainer.speed;
+        if !trainer.advance(info.pos, a, b, Instant::now()) {
+            return;
+        }
+        let speed = trainer.speed;
+        if speed != speed_before {
+            logln!("Practice trainer: speed {:.0}%", speed * 100.0);
+        }
+        let count_in = trainer.cfg.count_in;
+        if count_in > 0.0 {
+            trainer.count_in_until = Some(Instant::now() + Duration::from_secs_f64(count_in));
+        }
+        self.mpv_handler
+            .ipc(|b| {
+                if speed != speed_before {
+                    b.set_speed(speed)?;
+                }
+                if count_in > 0.0 {
+                    b.set_pause(true)?;
+                }
+                Ok(())
+            })
+            .err_popup("Practice trainer error", modal);
     }
 
     pub(crate) fn seek(&mut self, pos: f64) -> anyhow::Result<()> {
         self.mpv_handler.ipc(|b| b.seek(pos)).unwrap_or(Ok(()))
     }
 
-    pub(crate) fn handle_event(&mut self, event: crate::ipc::IpcEvent) {
+    /// Seek forward (or backward, if negative) by `offset` seconds
+    pub(crate) fn seek_relative(&mut self, offset: f64) -> anyhow::Result<()> {
+        let Some(info) = self.mpv_handler.time_info() else {
+            return Ok(());
+        };
+        self.seek((info.pos + offset).clamp(0.0, info.duration.max(0.0)))
+    }
+
+    pub(crate) fn handle_event(&mut self, event: IpcEvent, modal: &mut ModalPopup) {
         match event {
-            crate::ipc::IpcEvent::EndFile => {
+            IpcEvent::CommandError {
+                command,
+                error,
+                quiet,
+            } => {
+                logln!("mpv command {command} failed: {error}");
+                if !quiet {
+                    modal.warn("mpv command failed", format!("{command}\n\n{error}"));
+                }
+            }
+            IpcEvent::EndFile { reason, file_error } => {
                 self.save_mpv_values_to_cfg();
+                if reason == EndFileReason::Eof
+                    && let Some((feed_url, guid)) = &self.playing_episode
+                {
+                    self.podcasts
+                        .set_state(feed_url, guid, EpisodeState::Played);
+                    self.podcasts.save();
+                }
+                if self.playing_book.is_some() {
+                    self.bookmarks.save();
+                }
+                match reason {
+                    EndFileReason::Error => {
+                        self.playback_error =
+                            Some(file_error.unwrap_or_else(|| "unknown error".into()));
+                    }
+                    // mpv keeps going after a redirect, so it doesn't tell us anything
+                    EndFileReason::Redirect => {}
+                    _ => self.playback_error = None,
+                }
             }
         }
     }
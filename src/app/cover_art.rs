@@ -0,0 +1,281 @@
+//! Cover art of songs, from embedded pictures or folder images, as cached thumbnails
+
+use {
+    crate::{
+        logln,
+        util::{
+            hash::fnv1a,
+            json_store::{self, Dir},
+            url,
+            worker::Worker,
+        },
+    },
+    egui_sf2g::egui,
+    std::{
+        fs::File,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+        time::SystemTime,
+    },
+};
+
+/// Width and height of the thumbnails
+const THUMB_SIZE: usize = 256;
+/// How many thumbnails are kept in memory
+const MAX_CACHED: usize = 16;
+/// How many thumbnails are kept on disk
+const MAX_ON_DISK: usize = 1000;
+
+pub struct CoverArt {
+    /// The thumbnail, ready to be turned into a texture
+    pub image: egui::ColorImage,
+    /// The thumbnail saved as PNG, for things that want a file (like `mpris:artUrl`)
+    pub file: PathBuf,
+}
+
+impl CoverArt {
+    /// The thumbnail as a `file://` URL
+    pub fn art_url(&self) -> String {
+        format!("file://{}", url::encode_path(&self.file))
+    }
+}
+
+#[derive(Default)]
+pub struct CoverArts {
+    /// Recently looked up songs (by absolute path), oldest first.
+    /// `None` means the song has no cover art.
+    cache: Vec<(PathBuf, Option<CoverArt>)>,
+    /// Songs being looked up
+    pending: Vec<PathBuf>,
+    worker: Option<Worker<PathBuf, Option<CoverArt>>>,
+}
+
+impl CoverArts {
+    pub fn get(&self, song: &Path) -> Option<&CoverArt> {
+        self.cache
+            .iter()
+            .find(|(path, _)| path == song)
+            .and_then(|(_, art)| art.as_ref())
+    }
+    /// Look up the cover art of a song in the background, unless already known
+    pub fn request(&mut self, song: &Path) {
+        if self.cache.iter().any(|(path, _)| path == song) || self.pending.iter().any(|p| p == song)
+        {
+            return;
+        }
+        let worker = self
+            .worker
+            .get_or_insert_with(|| Worker::new("Cover art", |song: &PathBuf| find_cover_art(song)));
+        if !worker.send(song.to_owned()) {
+            return;
+        }
+        self.pending.push(song.to_owned());
+    }
+    /// Collect the looked up cover art
+    pub fn update(&mut self) {
+        let Some(worker) = &self.worker else {
+            return;
+        };
+        for (song, art) in worker.results() {
+            self.pending.retain(|p| *p != song);
+            self.cache.push((song, art));
+        }
+        if self.cache.len() > MAX_CACHED {
+            self.cache.drain(..self.cache.len() - MAX_CACHED);
+        }
+    }
+}
+
+/// A texture of the cover art of the current song.
+///
+/// Textures belong to an egui context, so each window showing cover art needs one of these.
+#[derive(Default)]
+pub struct CoverTexture {
+    song: Option<PathBuf>,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl CoverTexture {
+    pub fn get(
+        &mut self,
+        ctx: &egui::Context,
+        covers: &CoverArts,
+        song: Option<&Path>,
+    ) -> Option<&egui::TextureHandle> {
+        let art = song.and_then(|song| covers.get(song));
+        // The cover art might have been looked up since the last call
+        if self.song.as_deref() != song || (self.texture.is_none() && art.is_some()) {
+            self.song = song.map(Path::to_owned);
+            self.texture = art.map(|art| {
+                ctx.load_texture("cover_art", art.image.clone(), egui::TextureOptions::LINEAR)
+            });
+        }
+        self.texture.as_ref()
+    }
+}
+
+/// Use the thumbnail made before if it's still fresh, otherwise make one.
+///
+/// For a new thumbnail, the embedded picture is tried first, then the images in the folder of
+/// the song.
+fn find_cover_art(song: &Path) -> Option<CoverArt> {
+    let dest = thumbnail_path(song)?;
+    if is_fresh(&dest, song) {
+        match load_thumbnail(&dest) {
+            Ok(image) => {
+                // Recently used thumbnails are the last ones to be pruned
+                if let Err(e) = File::options()
+                    .write(true)
+                    .open(&dest)
+                    .and_then(|file| file.set_modified(SystemTime::now()))
+                {
+                    logln!("Failed to touch thumbnail {}: {e}", dest.display());
+                }
+                return Some(CoverArt { image, file: dest });
+            }
+            Err(e) => logln!("Failed to load thumbnail {}: {e:#}", dest.display()),
+        }
+    }
+    let covers_dir = dest.parent()?;
+    if let Err(e) = std::fs::create_dir_all(covers_dir) {
+        logln!("Failed to create cover art cache dir: {e}");
+        return None;
+    }
+    prune(covers_dir);
+    // Embedded pictures are video streams for ffmpeg. Most songs don't have any, that's fine.
+    if let Ok(image) = make_thumbnail(song, &dest) {
+        return Some(CoverArt { image, file: dest });
+    }
+    let folder_image = folder_image(song.parent()?)?;
+    match make_thumbnail(&folder_image, &dest) {
+        Ok(image) => Some(CoverArt { image, file: dest }),
+        Err(e) => {
+            logln!("Failed to load cover art {}: {e:#}", folder_image.display());
+            None
+        }
+    }
+}
+
+/// Whether the thumbnail exists, and was made after the song and its folder last changed
+fn is_fresh(thumbnail: &Path, song: &Path) -> bool {
+    let modified = |path: &Path| {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+    let Some(made) = modified(thumbnail) else {
+        return false;
+    };
+    [Some(song), song.parent()]
+        .into_iter()
+        .flatten()
+        .all(|path| modified(path).is_some_and(|changed| changed <= made))
+}
+
+/// Remove the least recently used thumbnails, so that there's room for a new one
+fn prune(covers_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(covers_dir) else {
+        return;
+    };
+    let mut thumbnails: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            Some((entry.metadata().ok()?.modified().ok()?, entry.path()))
+        })
+        .collect();
+    if thumbnails.len() < MAX_ON_DISK {
+        return;
+    }
+    thumbnails.sort();
+    for (_, path) in &thumbnails[..=thumbnails.len() - MAX_ON_DISK] {
+        if let Err(e) = std::fs::remove_file(path) {
+            logln!("Failed to remove thumbnail {}: {e}", path.display());
+        }
+    }
+}
+
+/// Find an image like `cover.jpg` or `Folder.png` in a folder
+fn folder_image(dir: &Path) -> Option<PathBuf> {
+    let mut images: Vec<(usize, PathBuf)> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let rank = cover_rank(path.file_name()?.to_str()?)?;
+            Some((rank, path))
+        })
+        .collect();
+    images.sort();
+    images.into_iter().next().map(|(_, path)| path)
+}
+
+/// How good of a cover image a file name suggests (lower is better), or `None` if it isn't one
+fn cover_rank(file_name: &str) -> Option<usize> {
+    let lower = file_name.to_ascii_lowercase();
+    let (stem, ext) = lower.rsplit_once('.')?;
+    if !["jpg", "jpeg", "png", "webp"].contains(&ext) {
+        return None;
+    }
+    ["cover", "folder", "front", "album", "albumart"]
+        .iter()
+        .position(|name| *name == stem)
+}
+
+fn thumbnail_path(song: &Path) -> Option<PathBuf> {
+    let hash = fnv1a(song.as_os_str().as_encoded_bytes());
+    let name = format!("{hash:016x}.png");
+    Some(json_store::path(Dir::Cache, "covers")?.join(name))
+}
+
+/// Scale the first picture of `input` down, saving it as PNG to `dest`, and also returning it
+fn make_thumbnail(input: &Path, dest: &Path) -> anyhow::Result<egui::ColorImage> {
+    let filter = format!(
+        "scale={THUMB_SIZE}:{THUMB_SIZE}:force_original_aspect_ratio=decrease,\
+         pad={THUMB_SIZE}:{THUMB_SIZE}:(ow-iw)/2:(oh-ih)/2:color=black@0,format=rgba"
+    );
+    let output_args = ["-map", "0:v:0", "-frames:v", "1", "-vf", filter.as_str()];
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-nostats", "-v", "error", "-y", "-i"])
+        .arg(input)
+        .args(output_args)
+        .arg(dest)
+        .args(output_args);
+    read_rgba(cmd)
+}
+
+/// Load a thumbnail saved by [`make_thumbnail`]
+fn load_thumbnail(path: &Path) -> anyhow::Result<egui::ColorImage> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-nostats", "-v", "error", "-i"])
+        .arg(path)
+        .args(["-frames:v", "1"]);
+    read_rgba(cmd)
+}
+
+/// Run an ffmpeg command, with a thumbnail as raw RGBA written to stdout
+fn read_rgba(mut cmd: Command) -> anyhow::Result<egui::ColorImage> {
+    let output = cmd
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last = stderr.lines().last().unwrap_or_default();
+        anyhow::bail!("ffmpeg exited with {}: {last}", output.status);
+    }
+    if output.stdout.len() != THUMB_SIZE * THUMB_SIZE * 4 {
+        anyhow::bail!("Unexpected thumbnail size: {} bytes", output.stdout.len());
+    }
+    Ok(egui::ColorImage::from_rgba_unmultiplied(
+        [THUMB_SIZE, THUMB_SIZE],
+        &output.stdout,
+    ))
+}
+
+#[test]
+fn test_cover_rank() {
+    assert_eq!(cover_rank("Cover.JPG"), Some(0));
+    assert_eq!(cover_rank("folder.png"), Some(1));
+    assert_eq!(cover_rank("cover.txt"), None);
+    assert_eq!(cover_rank("back.jpg"), None);
+    assert_eq!(cover_rank("jpg"), None);
+}
@@ -0,0 +1,49 @@
+//! Timed volume ramps for smooth playback transitions
+
+use std::time::{Duration, Instant};
+
+pub struct Fade {
+    start: Instant,
+    duration: Duration,
+    from: f64,
+    to: f64,
+    /// The volume the user actually wants.
+    ///
+    /// This is what gets saved to the config, instead of the transient fade volume.
+    pub user_volume: u8,
+    /// What to do once the fade is finished
+    pub then: FadeThen,
+}
+
+pub enum FadeThen {
+    Nothing,
+    Pause,
+    Stop,
+    PlaySelected,
+}
+
+impl Fade {
+    pub fn new(from: f64, to: f64, duration_ms: u32, user_volume: u8, then: FadeThen) -> Self {
+        Self {
+            start: Instant::now(),
+            duration: Duration::from_millis(duration_ms.into()),
+            from,
+            to,
+            user_volume,
+            then,
+        }
+    }
+    fn progress(&self) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        (self.start.elapsed().as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+    }
+    /// The volume at the current point of the fade
+    pub fn volume(&self) -> f64 {
+        self.from + (self.to - self.from) * self.progress()
+    }
+    pub fn finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+}
@@ -0,0 +1,82 @@
+//! Named A-B loop sections, saved per file
+
+use {
+    crate::util::json_store::{self, Dir},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    },
+};
+
+const FILE_NAME: &str = "loops.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoopSection {
+    pub name: String,
+    /// Start, in seconds
+    pub a: f64,
+    /// End, in seconds
+    pub b: f64,
+}
+
+#[derive(Default)]
+pub struct LoopStore {
+    /// Keyed by absolute path
+    sections: HashMap<PathBuf, Vec<LoopSection>>,
+}
+
+impl LoopStore {
+    pub fn load() -> Self {
+        Self {
+            sections: json_store::load(Dir::Data, FILE_NAME, "loop sections"),
+        }
+    }
+    fn save(&self) {
+        json_store::save(Dir::Data, FILE_NAME, "loop sections", &self.sections);
+    }
+    pub fn get(&self, file: &Path) -> &[LoopSection] {
+        self.sections.get(file).map_or(&[], Vec::as_slice)
+    }
+    /// Add a section, replacing the one with the same name if it exists
+    pub fn insert(&mut self, file: &Path, section: LoopSection) {
+        self.insert_no_save(file, section);
+        self.save();
+    }
+    fn insert_no_save(&mut self, file: &Path, section: LoopSection) {
+        let sections = self.sections.entry(file.to_owned()).or_default();
+        match sections.iter_mut().find(|s| s.name == section.name) {
+            Some(existing) => *existing = section,
+            None => sections.push(section),
+        }
+        sections.sort_by(|s1, s2| s1.a.total_cmp(&s2.a));
+    }
+    pub fn remove(&mut self, file: &Path, idx: usize) {
+        if let Some(sections) = self.sections.get_mut(file)
+            && idx < sections.len()
+        {
+            sections.remove(idx);
+            if sections.is_empty() {
+                self.sections.remove(file);
+            }
+            self.save();
+        }
+    }
+    /// Write the sections of a file to a JSON file
+    pub fn export(&self, file: &Path, json_path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_vec_pretty(self.get(file))?;
+        std::fs::write(json_path, json)?;
+        Ok(())
+    }
+    /// Read sections for a file from a JSON file. Returns how many sections were imported.
+    pub fn import(&mut self, file: &Path, json_path: &Path) -> anyhow::Result<usize> {
+        let data = std::fs::read(json_path)?;
+        let imported: Vec<LoopSection> = serde_json::from_slice(&data)?;
+        let count = imported.len();
+        for section in imported {
+            self.insert_no_save(file, section);
+        }
+        self.save();
+        Ok(count)
+    }
+}
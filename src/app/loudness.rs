@@ -0,0 +1,131 @@
+//! Background EBU R128 loudness analysis, for files without ReplayGain tags
+
+use {
+    crate::{
+        logln,
+        util::{
+            json_store::{self, Dir},
+            worker::Worker,
+        },
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    },
+};
+
+const FILE_NAME: &str = "loudness.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum Analysis {
+    /// The file has ReplayGain tags, so mpv can take care of it
+    Tagged,
+    /// Integrated loudness in LUFS
+    Loudness(f64),
+}
+
+/// Results of the analysis, cached across runs
+#[derive(Default)]
+pub struct Loudness {
+    /// Keyed by absolute path
+    cache: HashMap<PathBuf, Analysis>,
+    worker: Option<Worker<PathBuf, anyhow::Result<Analysis>>>,
+    /// Number of files queued since the worker was last idle
+    queued: usize,
+    /// Number of queued files that were analyzed so far
+    done: usize,
+}
+
+impl Loudness {
+    pub fn load() -> Self {
+        Self {
+            cache: json_store::load(Dir::Cache, FILE_NAME, "loudness cache"),
+            ..Self::default()
+        }
+    }
+    pub fn save(&self) {
+        json_store::save(Dir::Cache, FILE_NAME, "loudness cache", &self.cache);
+    }
+    pub fn get(&self, path: &Path) -> Option<Analysis> {
+        self.cache.get(path).copied()
+    }
+    /// Queue the files that weren't analyzed yet for analysis
+    pub fn analyze(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        let worker = self.worker.get_or_insert_with(|| {
+            Worker::new("Loudness analysis", |path: &PathBuf| analyze_file(path))
+        });
+        for path in paths {
+            if self.cache.contains_key(&path) {
+                continue;
+            }
+            if !worker.send(path) {
+                return;
+            }
+            self.queued += 1;
+        }
+    }
+    /// Collect the results of the analysis
+    pub fn update(&mut self) {
+        let Some(worker) = &self.worker else {
+            return;
+        };
+        let mut got_results = false;
+        for (path, result) in worker.results() {
+            got_results = true;
+            self.done += 1;
+            match result {
+                Ok(analysis) => {
+                    self.cache.insert(path, analysis);
+                }
+                Err(e) => logln!("Loudness analysis of {} failed: {e:#}", path.display()),
+            }
+        }
+        if got_results && self.done >= self.queued {
+            self.queued = 0;
+            self.done = 0;
+            self.save();
+        }
+    }
+    /// `(done, queued)` if an analysis is in progress
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        (self.queued != 0).then_some((self.done, self.queued))
+    }
+}
+
+fn analyze_file(path: &Path) -> anyhow::Result<Analysis> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(path)
+        .args([
+            "-map",
+            "0:a:0",
+            "-af",
+            "ebur128=framelog=verbose",
+            "-f",
+            "null",
+            "-",
+        ])
+        .stdin(Stdio::null())
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        let last = stderr.lines().last().unwrap_or_default();
+        anyhow::bail!("ffmpeg exited with {}: {last}", output.status);
+    }
+    parse_ffmpeg_output(&stderr).ok_or_else(|| anyhow::anyhow!("No loudness in ffmpeg output"))
+}
+
+fn parse_ffmpeg_output(stderr: &str) -> Option<Analysis> {
+    let lower = stderr.to_ascii_lowercase();
+    // Shows up in the metadata, or the side data of the stream
+    if lower.contains("replaygain_track_gain") || lower.contains("replaygain: track gain") {
+        return Some(Analysis::Tagged);
+    }
+    let (_, summary) = stderr.rsplit_once("Integrated loudness:")?;
+    let line = summary.lines().find_map(|l| l.trim().strip_prefix("I:"))?;
+    let lufs: f64 = line.trim().strip_suffix("LUFS")?.trim().parse().ok()?;
+    // Silence has a loudness of -inf
+    lufs.is_finite().then_some(Analysis::Loudness(lufs))
+}
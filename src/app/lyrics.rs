@@ -0,0 +1,170 @@
+//! Song lyrics, from sidecar `.lrc`/`.txt` files or embedded tags
+
+use {
+    crate::logln,
+    std::path::{Path, PathBuf},
+};
+
+pub struct Lyrics {
+    pub lines: Vec<Line>,
+    /// Whether the lines have timestamps (synced LRC)
+    pub timed: bool,
+    /// Where the lyrics came from, for display
+    pub source: Source,
+}
+
+pub struct Line {
+    /// When the line starts, in seconds
+    pub time: Option<f64>,
+    pub text: String,
+}
+
+pub enum Source {
+    File(PathBuf),
+    Embedded,
+}
+
+impl Lyrics {
+    /// Load the lyrics file next to a song, if there is one
+    pub fn load_sidecar(song_path: &Path) -> Option<Self> {
+        let path = ["lrc", "txt"]
+            .into_iter()
+            .map(|ext| song_path.with_extension(ext))
+            .find(|path| path.is_file())?;
+        match std::fs::read(&path) {
+            Ok(data) => Some(parse(&String::from_utf8_lossy(&data), Source::File(path))),
+            Err(e) => {
+                logln!("Failed to read lyrics {}: {e}", path.display());
+                None
+            }
+        }
+    }
+    /// Index of the line being sung at `pos`
+    pub fn current_line(&self, pos: f64) -> Option<usize> {
+        if !self.timed {
+            return None;
+        }
+        self.lines
+            .iter()
+            .rposition(|line| line.time.is_some_and(|time| time <= pos))
+    }
+}
+
+/// Parse LRC, falling back to plain text when there are no timestamps
+pub fn parse(text: &str, source: Source) -> Lyrics {
+    let mut offset = 0.0;
+    let mut lines = Vec::new();
+    let mut timed = false;
+    for raw in text.lines() {
+        let raw = raw.trim();
+        let mut rest = raw;
+        let mut times = Vec::new();
+        while let Some(after) = rest.strip_prefix('[') {
+            let Some(end) = after.find(']') else {
+                break;
+            };
+            let tag = &after[..end];
+            match parse_timestamp(tag) {
+                Some(time) => times.push(time),
+                None => {
+                    // Metadata tags, like [ar:Artist]. Only the offset matters.
+                    // They are dropped with the other untimed lines if the lyrics are synced,
+                    // otherwise it's probably just text, like [Verse 1: Artist].
+                    let Some((key, value)) = tag.split_once(':') else {
+                        break;
+                    };
+                    if key.trim().eq_ignore_ascii_case("offset")
+                        && let Ok(ms) = value.trim().parse::<f64>()
+                    {
+                        offset = ms / 1000.0;
+                    }
+                }
+            }
+            rest = &after[end + 1..];
+        }
+        if times.is_empty() {
+            lines.push(Line {
+                time: None,
+                text: raw.to_owned(),
+            });
+            continue;
+        }
+        timed = true;
+        let text = rest.trim();
+        // A line can have several timestamps, when it is repeated
+        lines.extend(times.into_iter().map(|time| Line {
+            time: Some(time),
+            text: text.to_owned(),
+        }));
+    }
+    if timed {
+        // Untimed lines in a synced file are usually junk, like credits
+        lines.retain(|line| line.time.is_some());
+        for line in &mut lines {
+            // A positive offset makes the lyrics appear sooner
+            line.time = line.time.map(|time| (time - offset).max(0.0));
+        }
+        lines.sort_by(|a, b| a.time.unwrap_or(0.0).total_cmp(&b.time.unwrap_or(0.0)));
+    } else {
+        // Trim leading and trailing blank lines
+        let start = lines.iter().position(|l| !l.text.is_empty()).unwrap_or(0);
+        let end = lines
+            .iter()
+            .rposition(|l| !l.text.is_empty())
+            .map_or(0, |i| i + 1);
+        lines = lines.drain(start..end.max(start)).collect();
+    }
+    Lyrics {
+        lines,
+        timed,
+        source,
+    }
+}
+
+/// Parse `mm:ss`, `mm:ss.xx` or `mm:ss:xx`
+fn parse_timestamp(tag: &str) -> Option<f64> {
+    let (min, sec) = tag.split_once(':')?;
+    let min: u32 = min.trim().parse().ok()?;
+    let sec = match sec.split_once(':') {
+        Some((sec, frac)) => format!("{sec}.{frac}"),
+        None => sec.to_owned(),
+    };
+    if !sec.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let sec: f64 = sec.parse().ok()?;
+    Some(f64::from(min) * 60.0 + sec)
+}
+
+#[test]
+fn test_parse_lrc() {
+    let lrc = "[ar:Frog]\n[offset:+500]\n[00:12.00]Ribbit\n\n[00:05.50][01:00.00]Croak\n";
+    let lyrics = parse(lrc, Source::Embedded);
+    assert!(lyrics.timed);
+    let lines: Vec<(Option<f64>, &str)> = lyrics
+        .lines
+        .iter()
+        .map(|line| (line.time, line.text.as_str()))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            (Some(5.0), "Croak"),
+            (Some(11.5), "Ribbit"),
+            (Some(59.5), "Croak")
+        ]
+    );
+    assert_eq!(lyrics.current_line(1.0), None);
+    assert_eq!(lyrics.current_line(12.0), Some(1));
+    let plain = parse(
+        "\n[Verse 1: Frog]\nFirst line\n\nSecond [line]\n\n",
+        Source::Embedded,
+    );
+    assert!(!plain.timed);
+    let texts: Vec<&str> = plain.lines.iter().map(|l| l.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        ["[Verse 1: Frog]", "First line", "", "Second [line]"]
+    );
+    assert_eq!(plain.current_line(5.0), None);
+}
@@ -1,18 +1,127 @@
-use {crate::config::Config, std::path::PathBuf, walkdir::WalkDir};
+pub mod archive;
+pub mod cue;
+
+use {
+    super::podcasts::Feed,
+    crate::{
+        config::{Config, Station},
+        logln,
+    },
+    cue::CueTrack,
+    std::{
+        borrow::Cow,
+        collections::HashSet,
+        path::{Path, PathBuf},
+    },
+    walkdir::WalkDir,
+};
 
 #[derive(Default)]
 pub struct Playlist {
     items: Vec<Item>,
+    archive_lister: archive::Lister,
+    /// Archives (relative to the music folder) that are being listed
+    pending_archives: Vec<PathBuf>,
 }
 
-pub struct Item {
-    pub path: PathBuf,
+#[derive(Clone, PartialEq)]
+pub enum Item {
+    /// A file, relative to the music folder
+    File(PathBuf),
+    /// A track of a CUE sheet, playing only a part of the file
+    CueTrack { path: PathBuf, track: CueTrack },
+    /// A file inside the archive at `archive` (relative to the music folder)
+    ArchiveEntry { archive: PathBuf, entry: PathBuf },
+    /// A stream, like an internet radio station
+    Url { name: String, url: String },
+    /// A podcast episode
+    Episode {
+        feed_url: String,
+        feed_title: String,
+        guid: String,
+        title: String,
+        /// Downloaded file, or URL
+        location: String,
+    },
 }
 
 impl Item {
-    fn new(path: PathBuf) -> Self {
-        Self { path }
+    /// The local file backing this item, relative to the music folder
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) | Self::CueTrack { path, .. } => Some(path),
+            Self::ArchiveEntry { archive, .. } => Some(archive),
+            Self::Url { .. } | Self::Episode { .. } => None,
+        }
+    }
+    /// Like [`Self::path`], but with archive entries appended to the archive path
+    pub fn virtual_path(&self) -> Option<Cow<'_, Path>> {
+        match self {
+            Self::ArchiveEntry { archive, entry } => Some(Cow::Owned(archive.join(entry))),
+            _ => self.path().map(Cow::Borrowed),
+        }
+    }
+    /// Name shown in the playlist
+    pub fn display_name(&self) -> Cow<'_, str> {
+        match self {
+            Self::File(path) => path.to_string_lossy(),
+            Self::CueTrack { path, track } => {
+                let mut name = format!("{} #{:02}", path.display(), track.number);
+                if let Some(performer) = &track.performer {
+                    name.push_str(&format!(" {performer} -"));
+                }
+                if let Some(title) = &track.title {
+                    name.push_str(&format!(" {title}"));
+                }
+                Cow::Owned(name)
+            }
+            Self::ArchiveEntry { archive, entry } => {
+                Cow::Owned(archive.join(entry).to_string_lossy().into_owned())
+            }
+            Self::Url { name, .. } => Cow::Owned(format!("📻 {name}")),
+            Self::Episode {
+                feed_title, title, ..
+            } => Cow::Owned(format!("🎙 {feed_title}: {title}")),
+        }
+    }
+    /// Short name, like the file name, or the title of a CUE track
+    pub fn short_name(&self) -> Option<&str> {
+        match self {
+            Self::File(path) => path.file_name()?.to_str(),
+            Self::CueTrack { path, track } => track
+                .title
+                .as_deref()
+                .or_else(|| path.file_name()?.to_str()),
+            Self::ArchiveEntry { entry, .. } => entry.file_name()?.to_str(),
+            Self::Url { name, .. } => Some(name),
+            Self::Episode { title, .. } => Some(title),
+        }
     }
+    pub fn is_stream(&self) -> bool {
+        matches!(self, Self::Url { .. })
+    }
+    /// Whether this is the same song as `other`, even if some details of it changed,
+    /// like the location of a podcast episode after downloading it
+    pub fn is_same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Episode { feed_url, guid, .. },
+                Self::Episode {
+                    feed_url: other_url,
+                    guid: other_guid,
+                    ..
+                },
+            ) => feed_url == other_url && guid == other_guid,
+            _ => self == other,
+        }
+    }
+}
+
+/// Files that are commonly next to songs, but aren't songs
+fn is_skipped(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ["jpg", "jpeg", "png", "webp", "txt", "lrc"].contains(&ext))
 }
 
 fn is_hidden(entry: &walkdir::DirEntry) -> bool {
@@ -25,10 +134,14 @@ fn is_hidden(entry: &walkdir::DirEntry) -> bool {
 
 impl Playlist {
     pub fn read_songs(&mut self, cfg: &Config) {
-        let Some(music_folder) = &cfg.music_folder else {
-            return;
-        };
         self.items.clear();
+        self.pending_archives.clear();
+        if let Some(music_folder) = &cfg.music_folder {
+            self.read_folder(cfg, music_folder);
+        }
+        self.set_stations(&cfg.stations);
+    }
+    fn read_folder(&mut self, cfg: &Config, music_folder: &Path) {
         let entries = WalkDir::new(music_folder)
             .follow_links(cfg.follow_symlinks)
             .into_iter()
@@ -39,25 +152,153 @@ impl Playlist {
                     true
                 }
             });
+        let mut cue_sheets = Vec::new();
         for entry in entries.filter_map(Result::ok) {
             if entry.file_type().is_file() {
                 let en_path = entry.path();
-                if let Some(ext) = en_path.extension().and_then(|ext| ext.to_str()) {
-                    if ["jpg", "png", "txt"]
-                        .into_iter()
-                        .any(|filter_ext| filter_ext == ext)
-                    {
-                        continue;
-                    }
+                if en_path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+                {
+                    cue_sheets.push(en_path.to_owned());
+                    continue;
+                }
+                if is_skipped(en_path) {
+                    continue;
                 }
                 let path = en_path.strip_prefix(music_folder).unwrap().to_owned();
-                self.items.push(Item::new(path));
+                // Their songs are added once they're listed
+                if cfg.scan_archives && archive::is_archive(en_path) {
+                    self.archive_lister
+                        .request((en_path.to_owned(), path.clone()));
+                    self.pending_archives.push(path);
+                    continue;
+                }
+                self.items.push(Item::File(path));
             }
         }
+        self.add_cue_tracks(music_folder, &cue_sheets);
         self.sort();
     }
-    pub fn sort(&mut self) {
-        self.items.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    /// Add the songs of the archives that were listed in the background.
+    ///
+    /// Returns whether the playlist changed.
+    pub fn collect_archive_listings(&mut self) -> bool {
+        let mut changed = false;
+        for ((full_path, path), result) in self.archive_lister.results() {
+            // Listings requested by a previous scan are ignored
+            let Some(pos) = self.pending_archives.iter().position(|p| *p == path) else {
+                continue;
+            };
+            self.pending_archives.swap_remove(pos);
+            let end = self.local_len();
+            match result {
+                Ok(entries) => {
+                    self.items.splice(
+                        end..end,
+                        entries
+                            .into_iter()
+                            .filter(|entry| !is_skipped(entry))
+                            .map(|entry| Item::ArchiveEntry {
+                                archive: path.clone(),
+                                entry,
+                            }),
+                    );
+                }
+                Err(e) => {
+                    logln!("Failed to list archive {}: {e:#}", full_path.display());
+                    self.items.insert(end, Item::File(path));
+                }
+            }
+            changed = true;
+        }
+        if changed {
+            self.sort();
+        }
+        changed
+    }
+    /// Replace the files split by CUE sheets with an item for each track
+    fn add_cue_tracks(&mut self, music_folder: &Path, cue_sheets: &[PathBuf]) {
+        let mut split_files = HashSet::new();
+        for sheet_path in cue_sheets {
+            let data = match std::fs::read(sheet_path) {
+                Ok(data) => data,
+                Err(e) => {
+                    logln!("Failed to read cue sheet {}: {e}", sheet_path.display());
+                    continue;
+                }
+            };
+            let dir = sheet_path.parent().unwrap_or(music_folder);
+            for track in cue::parse(&cue::decode(&data)) {
+                let file = dir.join(&track.file);
+                let Ok(rel_path) = file.strip_prefix(music_folder) else {
+                    continue;
+                };
+                // Sheets can refer to files that were converted to another format since
+                if !file.exists() {
+                    continue;
+                }
+                split_files.insert(rel_path.to_owned());
+                self.items.push(Item::CueTrack {
+                    path: rel_path.to_owned(),
+                    track,
+                });
+            }
+        }
+        self.items
+            .retain(|item| !matches!(item, Item::File(path) if split_files.contains(path)));
+    }
+    /// Replace the podcast episodes in the playlist. They are listed last.
+    pub fn set_episodes(&mut self, feeds: &[Feed]) {
+        self.items
+            .retain(|item| !matches!(item, Item::Episode { .. }));
+        for feed in feeds {
+            self.items
+                .extend(feed.episodes.iter().map(|episode| Item::Episode {
+                    feed_url: feed.url.clone(),
+                    feed_title: feed.title.clone(),
+                    guid: episode.guid.clone(),
+                    title: episode.title.clone(),
+                    location: episode.location(),
+                }));
+        }
+    }
+    /// Replace the stations in the playlist. They are listed after the local songs.
+    pub fn set_stations(&mut self, stations: &[Station]) {
+        self.items.retain(|item| !item.is_stream());
+        // Before the episodes, so their indices don't change when the episodes are refreshed
+        let pos = self
+            .items
+            .iter()
+            .position(|item| matches!(item, Item::Episode { .. }))
+            .unwrap_or(self.items.len());
+        self.items.splice(
+            pos..pos,
+            stations.iter().map(|station| Item::Url {
+                name: station.name.clone(),
+                url: station.url.clone(),
+            }),
+        );
+    }
+    /// Number of local songs. They come before the stations and episodes.
+    fn local_len(&self) -> usize {
+        self.items
+            .iter()
+            .position(|item| matches!(item, Item::Url { .. } | Item::Episode { .. }))
+            .unwrap_or(self.items.len())
+    }
+    /// Sort the local songs by path
+    fn sort(&mut self) {
+        let end = self.local_len();
+        self.items[..end].sort_unstable_by(|a, b| {
+            a.virtual_path().cmp(&b.virtual_path()).then_with(|| {
+                let start = |item: &Item| match item {
+                    Item::CueTrack { track, .. } => track.start,
+                    _ => 0.0,
+                };
+                start(a).total_cmp(&start(b))
+            })
+        });
     }
     pub fn get(&self, idx: usize) -> Option<&Item> {
         self.items.get(idx)
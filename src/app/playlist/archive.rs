@@ -0,0 +1,233 @@
+//! Audio files inside zip/7z/rar archives, listed and extracted with `7z`
+
+use {
+    crate::util::{result_ext::LogErrExt as _, url, worker::Worker},
+    directories::ProjectDirs,
+    std::{
+        fs::DirBuilder,
+        os::unix::fs::DirBuilderExt as _,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    },
+};
+
+pub const EXTENSIONS: [&str; 3] = ["zip", "7z", "rar"];
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|a| a.eq_ignore_ascii_case(ext)))
+}
+
+/// Paths of the files inside an archive
+pub fn list(archive: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let output = Command::new("7z")
+        .args(["l", "-slt", "-ba"])
+        .arg(archive)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last = stderr.lines().last().unwrap_or_default();
+        anyhow::bail!("7z exited with {}: {last}", output.status);
+    }
+    Ok(parse_listing(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse the technical listing (`-slt`) of 7z, which has a block of `Key = Value` lines per entry
+fn parse_listing(listing: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for block in listing.split("\n\n") {
+        let mut path = None;
+        let mut is_dir = false;
+        for line in block.lines() {
+            match line.split_once(" = ") {
+                Some(("Path", value)) => path = Some(value),
+                Some(("Folder", "+")) => is_dir = true,
+                Some(("Attributes", attrs)) if attrs.starts_with('D') => is_dir = true,
+                _ => {}
+            }
+        }
+        if let Some(path) = path
+            && !is_dir
+        {
+            files.push(PathBuf::from(path));
+        }
+    }
+    files
+}
+
+/// `(full path, path relative to the music folder)` of an archive to list
+pub type ListJob = (PathBuf, PathBuf);
+
+/// Lists archives in the background, so scanning the music folder doesn't wait for 7z
+#[derive(Default)]
+pub struct Lister {
+    worker: Option<Worker<ListJob, anyhow::Result<Vec<PathBuf>>>>,
+}
+
+impl Lister {
+    pub fn request(&mut self, job: ListJob) {
+        let worker = self.worker.get_or_insert_with(|| {
+            Worker::new("Archive listing", |(full_path, _): &ListJob| {
+                list(full_path)
+            })
+        });
+        worker.send(job);
+    }
+    /// The listings that are done since the last call
+    pub fn results(&self) -> Vec<(ListJob, anyhow::Result<Vec<PathBuf>>)> {
+        self.worker
+            .as_ref()
+            .map(|worker| worker.results().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// `(archive, entry)`
+type Entry = (PathBuf, PathBuf);
+
+/// Extracts files from archives in the background, for tools that need a real file.
+///
+/// The files go into a directory private to this instance, and only the last extracted file
+/// is kept around.
+#[derive(Default)]
+pub struct Extractor {
+    dir: Option<PathBuf>,
+    worker: Option<Worker<Entry, anyhow::Result<PathBuf>>>,
+    /// The entry being extracted
+    pending: Option<Entry>,
+    /// The last finished extraction, until it's taken
+    done: Option<(Entry, anyhow::Result<PathBuf>)>,
+}
+
+impl Extractor {
+    /// The extracted file, if `entry` of `archive` was extracted.
+    ///
+    /// Otherwise the extraction is started (unless it's already in progress), and `None` is
+    /// returned. [`Self::update`] tells when it's done.
+    pub fn get_or_request(
+        &mut self,
+        archive: &Path,
+        entry: &Path,
+    ) -> Option<anyhow::Result<PathBuf>> {
+        let is_this = |(a, e): &Entry| a == archive && e == entry;
+        if self.done.as_ref().is_some_and(|(done, _)| is_this(done)) {
+            return self.done.take().map(|(_, result)| result);
+        }
+        if self.pending.as_ref().is_some_and(is_this) {
+            return None;
+        }
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => match create_private_dir() {
+                Ok(dir) => self.dir.insert(dir).clone(),
+                Err(e) => return Some(Err(e.context("Failed to create extraction dir"))),
+            },
+        };
+        let worker = self.worker.get_or_insert_with(|| {
+            Worker::new("Archive extraction", move |(archive, entry): &Entry| {
+                extract(&dir, archive, entry)
+            })
+        });
+        if !worker.send((archive.to_owned(), entry.to_owned())) {
+            return Some(Err(anyhow::anyhow!("Archive extraction worker went away")));
+        }
+        self.pending = Some((archive.to_owned(), entry.to_owned()));
+        None
+    }
+    /// Collect finished extractions. Returns whether the pending one is done.
+    pub fn update(&mut self) -> bool {
+        let Some(worker) = &self.worker else {
+            return false;
+        };
+        let mut finished = false;
+        for (entry, result) in worker.results() {
+            // Extractions that were superseded by another one are of no use
+            if self.pending.as_ref() == Some(&entry) {
+                self.pending = None;
+                self.done = Some((entry, result));
+                finished = true;
+            }
+        }
+        finished
+    }
+    /// Remove the extracted files
+    pub fn cleanup(&self) {
+        if let Some(dir) = &self.dir {
+            std::fs::remove_dir_all(dir).log_err("Failed to remove archive extraction dir");
+        }
+    }
+}
+
+/// Create a directory that only the current user can access, preferably in `$XDG_RUNTIME_DIR`.
+///
+/// It's unique to this instance, and creating it fails if it already exists, so no one else
+/// can sneak in their own.
+fn create_private_dir() -> anyhow::Result<PathBuf> {
+    let runtime_dir = ProjectDirs::from("", "crumblingstatue", "mpvfrog")
+        .and_then(|dirs| dirs.runtime_dir().map(Path::to_owned))
+        .filter(|dir| {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .is_ok()
+        });
+    let suffix: u32 = rand::random();
+    let name = format!("mpvfrog-archive-{}-{suffix:08x}", std::process::id());
+    let dir = runtime_dir.unwrap_or_else(std::env::temp_dir).join(name);
+    DirBuilder::new().mode(0o700).create(&dir)?;
+    Ok(dir)
+}
+
+/// Extract a file from an archive into `dir`, replacing what was extracted before
+fn extract(dir: &Path, archive: &Path, entry: &Path) -> anyhow::Result<PathBuf> {
+    for old in std::fs::read_dir(dir)? {
+        std::fs::remove_file(old?.path())?;
+    }
+    let file_name = entry
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Archive entry has no file name"))?;
+    let out_path = dir.join(file_name);
+    let out_file = std::fs::File::create_new(&out_path)?;
+    let status = Command::new("7z")
+        .args(["e", "-so"])
+        .arg(archive)
+        .arg(entry)
+        .stdin(Stdio::null())
+        .stdout(out_file)
+        .stderr(Stdio::null())
+        .status()?;
+    anyhow::ensure!(status.success(), "7z exited with {status}");
+    Ok(out_path)
+}
+
+/// URL for mpv's `archive://` protocol
+pub fn mpv_url(archive: &Path, entry: &Path) -> String {
+    let mut url = format!("archive://{}|/", url::encode_path(archive));
+    url.push_str(&entry.to_string_lossy());
+    url
+}
+
+#[test]
+fn test_parse_listing() {
+    let listing = "Path = music
+Folder = +
+Size = 0
+
+Path = music/track 1.xm
+Folder = -
+Size = 12345
+
+Path = readme.txt
+Attributes = A
+";
+    assert_eq!(
+        parse_listing(listing),
+        [
+            PathBuf::from("music/track 1.xm"),
+            PathBuf::from("readme.txt")
+        ]
+    );
+}
@@ -0,0 +1,153 @@
+//! Parsing of CUE sheets, which split a single audio file into tracks
+
+use std::{borrow::Cow, path::PathBuf};
+
+/// A track of a CUE sheet
+#[derive(Clone, PartialEq)]
+pub struct CueTrack {
+    /// The audio file, as written in the sheet (relative to the sheet)
+    pub file: PathBuf,
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Start, in seconds
+    pub start: f64,
+    /// End, in seconds. `None` if the track lasts until the end of the file.
+    pub end: Option<f64>,
+}
+
+/// Decode the contents of a CUE sheet.
+///
+/// Sheets are often written by old Windows software, so if the data isn't valid UTF-8,
+/// it's decoded as Latin-1 instead.
+pub fn decode(data: &[u8]) -> Cow<'_, str> {
+    match std::str::from_utf8(data) {
+        Ok(text) => Cow::Borrowed(text),
+        Err(_) => Cow::Owned(data.iter().copied().map(char::from).collect()),
+    }
+}
+
+/// Parse the tracks of a CUE sheet.
+///
+/// Tracks without an `INDEX 01` are skipped.
+pub fn parse(text: &str) -> Vec<CueTrack> {
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    let mut file = None;
+    let mut album_performer = None;
+    let mut current: Option<CueTrack> = None;
+    let mut has_start = false;
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match keyword {
+            "FILE" => {
+                finish_track(&mut tracks, current.take(), has_start);
+                file = Some(PathBuf::from(unquote(
+                    rest.rsplit_once(' ').map_or(rest, |(name, _ty)| name),
+                )));
+            }
+            "TRACK" => {
+                finish_track(&mut tracks, current.take(), has_start);
+                let Some(file) = &file else {
+                    continue;
+                };
+                has_start = false;
+                current = Some(CueTrack {
+                    file: file.clone(),
+                    number: rest
+                        .split(' ')
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(0),
+                    title: None,
+                    performer: album_performer.clone(),
+                    start: 0.0,
+                    end: None,
+                });
+            }
+            "TITLE" => {
+                if let Some(track) = &mut current {
+                    track.title = Some(unquote(rest).to_owned());
+                }
+            }
+            "PERFORMER" => match &mut current {
+                Some(track) => track.performer = Some(unquote(rest).to_owned()),
+                None => album_performer = Some(unquote(rest).to_owned()),
+            },
+            "INDEX" => {
+                if let Some(track) = &mut current
+                    && let Some(("01", time)) = rest.split_once(' ')
+                    && let Some(start) = parse_time(time)
+                {
+                    track.start = start;
+                    has_start = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    finish_track(&mut tracks, current, has_start);
+    // A track ends where the next one in the same file starts
+    for i in 1..tracks.len() {
+        if tracks[i].file == tracks[i - 1].file {
+            tracks[i - 1].end = Some(tracks[i].start);
+        }
+    }
+    tracks
+}
+
+fn finish_track(tracks: &mut Vec<CueTrack>, track: Option<CueTrack>, has_start: bool) {
+    if let Some(track) = track
+        && has_start
+    {
+        tracks.push(track);
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+/// Parse a `mm:ss:ff` timestamp, where `ff` is frames (75 per second)
+fn parse_time(s: &str) -> Option<f64> {
+    let mut parts = s.trim().split(':');
+    let min: u32 = parts.next()?.parse().ok()?;
+    let sec: u32 = parts.next()?.parse().ok()?;
+    let frames: u32 = parts.next()?.parse().ok()?;
+    Some(f64::from(min) * 60.0 + f64::from(sec) + f64::from(frames) / 75.0)
+}
+
+#[test]
+fn test_parse() {
+    let sheet = "\u{feff}PERFORMER \"Band\"
+TITLE \"Album\"
+FILE \"Album Name.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"First\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Second\"
+    PERFORMER \"Guest\"
+    INDEX 00 03:58:00
+    INDEX 01 04:00:15
+";
+    let tracks = parse(sheet);
+    assert_eq!(tracks.len(), 2);
+    assert_eq!(tracks[0].file, PathBuf::from("Album Name.flac"));
+    assert_eq!(tracks[0].title.as_deref(), Some("First"));
+    assert_eq!(tracks[0].performer.as_deref(), Some("Band"));
+    assert_eq!(tracks[0].end, Some(240.2));
+    assert_eq!(tracks[1].number, 2);
+    assert_eq!(tracks[1].performer.as_deref(), Some("Guest"));
+    assert_eq!(tracks[1].start, 240.2);
+    assert_eq!(tracks[1].end, None);
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode("Caf\u{e9}".as_bytes()), "Caf\u{e9}");
+    assert_eq!(decode(b"Caf\xe9 \xc6ther"), "Caf\u{e9} \u{c6}ther");
+}
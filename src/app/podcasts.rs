@@ -0,0 +1,380 @@
+//! Podcast feed subscriptions, with episode downloads and listening progress
+
+mod feed;
+
+use {
+    crate::{
+        config::PodcastConfig,
+        logln,
+        util::{
+            hash::fnv1a,
+            json_store::{self, Dir},
+            worker::Worker,
+        },
+    },
+    feed::ParsedFeed,
+    serde::{Deserialize, Serialize},
+    std::{
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+        time::{Duration, SystemTime},
+    },
+};
+
+const FILE_NAME: &str = "podcasts.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct Feed {
+    pub url: String,
+    pub title: String,
+    pub episodes: Vec<Episode>,
+    /// When the feed was last fetched (even if that failed)
+    #[serde(default)]
+    pub last_refresh: Option<SystemTime>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Episode {
+    pub guid: String,
+    pub title: String,
+    pub published: Option<String>,
+    pub notes: String,
+    pub enclosure: String,
+    /// Path of the downloaded file
+    #[serde(default)]
+    pub downloaded: Option<PathBuf>,
+    #[serde(default)]
+    pub state: EpisodeState,
+}
+
+impl Episode {
+    /// What to give to mpv to play the episode: the downloaded file, or the URL
+    pub fn location(&self) -> String {
+        match &self.downloaded {
+            Some(path) => path.to_string_lossy().into_owned(),
+            None => self.enclosure.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+pub enum EpisodeState {
+    #[default]
+    New,
+    /// Listened to up to this position, in seconds
+    InProgress(f64),
+    Played,
+}
+
+enum Job {
+    Refresh(String),
+    Download {
+        feed_url: String,
+        guid: String,
+        url: String,
+        dest: PathBuf,
+    },
+}
+
+enum JobResult {
+    Refreshed(String, anyhow::Result<ParsedFeed>),
+    Downloaded {
+        feed_url: String,
+        guid: String,
+        result: anyhow::Result<PathBuf>,
+    },
+}
+
+fn run(job: &Job) -> JobResult {
+    match job {
+        Job::Refresh(url) => {
+            let result = fetch(url).and_then(|xml| feed::parse(&xml));
+            JobResult::Refreshed(url.clone(), result)
+        }
+        Job::Download {
+            feed_url,
+            guid,
+            url,
+            dest,
+        } => JobResult::Downloaded {
+            feed_url: feed_url.clone(),
+            guid: guid.clone(),
+            result: download(url, dest).map(|()| dest.clone()),
+        },
+    }
+}
+
+#[derive(Default)]
+pub struct Podcasts {
+    pub feeds: Vec<Feed>,
+    worker: Option<Worker<Job, JobResult>>,
+    /// Feeds being refreshed, by URL
+    refreshing: Vec<String>,
+    /// Episodes being downloaded, by guid
+    downloading: Vec<String>,
+}
+
+impl Podcasts {
+    pub fn load() -> Self {
+        Self {
+            feeds: json_store::load(Dir::Data, FILE_NAME, "podcasts"),
+            ..Self::default()
+        }
+    }
+    pub fn save(&self) {
+        json_store::save(Dir::Data, FILE_NAME, "podcasts", &self.feeds);
+    }
+    fn send(&mut self, job: Job) {
+        let worker = self
+            .worker
+            .get_or_insert_with(|| Worker::new("Podcast", run));
+        worker.send(job);
+    }
+    pub fn subscribe(&mut self, url: String) {
+        if self.feeds.iter().any(|feed| feed.url == url) {
+            return;
+        }
+        self.feeds.push(Feed {
+            title: url.clone(),
+            url: url.clone(),
+            episodes: Vec::new(),
+            last_refresh: None,
+        });
+        self.refresh(url);
+    }
+    pub fn unsubscribe(&mut self, url: &str) {
+        self.feeds.retain(|feed| feed.url != url);
+        self.save();
+    }
+    pub fn refresh(&mut self, url: String) {
+        if self.refreshing.contains(&url) {
+            return;
+        }
+        self.refreshing.push(url.clone());
+        self.send(Job::Refresh(url));
+    }
+    pub fn is_refreshing(&self, url: &str) -> bool {
+        self.refreshing.iter().any(|u| u == url)
+    }
+    pub fn download(&mut self, cfg: &PodcastConfig, feed_url: &str, guid: &str) {
+        let Some(feed) = self.feeds.iter().find(|feed| feed.url == feed_url) else {
+            return;
+        };
+        let Some(episode) = feed.episodes.iter().find(|ep| ep.guid == guid) else {
+            return;
+        };
+        let Some(dir) = cfg.download_dir.clone().or_else(default_download_dir) else {
+            logln!("No podcast download directory");
+            return;
+        };
+        let dest = dir
+            .join(sanitize(&feed.title))
+            .join(download_file_name(guid, &episode.enclosure));
+        let job = Job::Download {
+            feed_url: feed_url.to_owned(),
+            guid: guid.to_owned(),
+            url: episode.enclosure.clone(),
+            dest,
+        };
+        self.downloading.push(guid.to_owned());
+        self.send(job);
+    }
+    pub fn is_downloading(&self, guid: &str) -> bool {
+        self.downloading.iter().any(|g| g == guid)
+    }
+    pub fn episode(&self, feed_url: &str, guid: &str) -> Option<&Episode> {
+        self.feeds
+            .iter()
+            .find(|feed| feed.url == feed_url)?
+            .episodes
+            .iter()
+            .find(|ep| ep.guid == guid)
+    }
+    fn episode_mut(&mut self, feed_url: &str, guid: &str) -> Option<&mut Episode> {
+        self.feeds
+            .iter_mut()
+            .find(|feed| feed.url == feed_url)?
+            .episodes
+            .iter_mut()
+            .find(|ep| ep.guid == guid)
+    }
+    pub fn set_state(&mut self, feed_url: &str, guid: &str, state: EpisodeState) {
+        if let Some(episode) = self.episode_mut(feed_url, guid) {
+            episode.state = state;
+        }
+    }
+    /// Collect results, and refresh the feeds that are due.
+    ///
+    /// Returns whether the episode list changed.
+    pub fn update(&mut self, cfg: &PodcastConfig) -> bool {
+        if cfg.refresh_interval != 0 {
+            let interval = Duration::from_secs(u64::from(cfg.refresh_interval) * 60);
+            let due: Vec<String> = self
+                .feeds
+                .iter()
+                .filter(|feed| {
+                    feed.last_refresh
+                        .and_then(|time| time.elapsed().ok())
+                        .is_none_or(|elapsed| elapsed >= interval)
+                })
+                .map(|feed| feed.url.clone())
+                .collect();
+            for url in due {
+                self.refresh(url);
+            }
+        }
+        let Some(worker) = &self.worker else {
+            return false;
+        };
+        let results: Vec<JobResult> = worker.results().map(|(_, result)| result).collect();
+        if results.is_empty() {
+            return false;
+        }
+        for result in results {
+            match result {
+                JobResult::Refreshed(url, result) => {
+                    self.refreshing.retain(|u| *u != url);
+                    let Some(feed) = self.feeds.iter_mut().find(|feed| feed.url == url) else {
+                        continue;
+                    };
+                    // Don't retry failed refreshes every frame
+                    feed.last_refresh = Some(SystemTime::now());
+                    match result {
+                        Ok(parsed) => merge(feed, parsed),
+                        Err(e) => logln!("Failed to refresh feed {url}: {e:#}"),
+                    }
+                }
+                JobResult::Downloaded {
+                    feed_url,
+                    guid,
+                    result,
+                } => {
+                    self.downloading.retain(|g| *g != guid);
+                    match result {
+                        Ok(path) => {
+                            if let Some(episode) = self.episode_mut(&feed_url, &guid) {
+                                episode.downloaded = Some(path);
+                            }
+                        }
+                        Err(e) => logln!("Failed to download episode: {e:#}"),
+                    }
+                }
+            }
+        }
+        self.save();
+        true
+    }
+}
+
+/// Update a feed with freshly fetched data, keeping the state of known episodes
+fn merge(feed: &mut Feed, parsed: ParsedFeed) {
+    if !parsed.title.is_empty() {
+        feed.title = parsed.title;
+    }
+    let mut old = std::mem::take(&mut feed.episodes);
+    feed.episodes = parsed
+        .episodes
+        .into_iter()
+        .map(|ep| {
+            let (downloaded, state) = match old.iter().position(|old| old.guid == ep.guid) {
+                Some(idx) => {
+                    let old = old.swap_remove(idx);
+                    (old.downloaded, old.state)
+                }
+                None => (None, EpisodeState::New),
+            };
+            Episode {
+                guid: ep.guid,
+                title: ep.title,
+                published: ep.published,
+                notes: ep.notes,
+                enclosure: ep.enclosure,
+                downloaded,
+                state,
+            }
+        })
+        .collect();
+    // Keep downloaded episodes that dropped out of the feed
+    feed.episodes
+        .extend(old.into_iter().filter(|ep| ep.downloaded.is_some()));
+}
+
+fn default_download_dir() -> Option<PathBuf> {
+    json_store::path(Dir::Data, "podcasts")
+}
+
+/// Make a string usable as a file name
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c == '/' || c.is_control() { '_' } else { c })
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "podcast".into(),
+        name => name.to_owned(),
+    }
+}
+
+/// File name for a downloaded episode.
+///
+/// Enclosure URLs often end in something generic like `/download` or `media.mp3?id=123`, so
+/// the name is derived from the guid, with the extension of the URL.
+fn download_file_name(guid: &str, url: &str) -> String {
+    let mut name = format!("{:016x}", fnv1a(guid.as_bytes()));
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let last_segment = path.rsplit('/').next().unwrap_or(path);
+    if let Some((_, ext)) = last_segment.rsplit_once('.')
+        && (1..=5).contains(&ext.len())
+        && ext.chars().all(|c| c.is_ascii_alphanumeric())
+    {
+        name.push('.');
+        name.push_str(ext);
+    }
+    name
+}
+
+fn fetch(url: &str) -> anyhow::Result<String> {
+    let output = Command::new("curl")
+        .args(["-sSfL", "--max-time", "60", "--"])
+        .arg(url)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("curl exited with {}: {}", output.status, stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn download(url: &str, dest: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Download to a temporary name, so a failed download doesn't look finished
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    let output = Command::new("curl")
+        .args(["-sSfL", "-o"])
+        .arg(&part)
+        .arg("--")
+        .arg(url)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&part);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("curl exited with {}: {}", output.status, stderr.trim());
+    }
+    std::fs::rename(&part, dest)?;
+    Ok(())
+}
+
+#[test]
+fn test_download_file_name() {
+    let a = download_file_name("ep-1", "https://example.com/media.mp3?id=1");
+    let b = download_file_name("ep-2", "https://example.com/media.mp3?id=2");
+    assert_ne!(a, b);
+    assert!(a.ends_with(".mp3"));
+    let c = download_file_name("ep-3", "https://example.com/episodes/3/download");
+    assert_eq!(c.len(), 16);
+}
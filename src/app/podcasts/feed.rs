@@ -0,0 +1,231 @@
+//! Parsing of RSS and Atom podcast feeds
+
+use roxmltree::{Document, ExpandedName, Node, ParsingOptions};
+
+/// The parts of a feed we care about
+pub struct ParsedFeed {
+    pub title: String,
+    pub episodes: Vec<ParsedEpisode>,
+}
+
+pub struct ParsedEpisode {
+    /// Unique id of the episode (falls back to the enclosure URL)
+    pub guid: String,
+    pub title: String,
+    /// Publication date, as written in the feed
+    pub published: Option<String>,
+    /// Show notes, with HTML tags removed
+    pub notes: String,
+    /// URL of the audio file
+    pub enclosure: String,
+}
+
+const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+/// Namespace of `content:encoded`, where RSS feeds often put the full show notes
+const CONTENT_NS: &str = "http://purl.org/rss/1.0/modules/content/";
+
+pub fn parse(xml: &str) -> anyhow::Result<ParsedFeed> {
+    let opts = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let doc = Document::parse_with_options(xml, opts)?;
+    let root = doc.root_element();
+    if let Some(channel) = child(root, "channel") {
+        // RSS
+        Ok(ParsedFeed {
+            title: child_text(channel, "title").unwrap_or_default(),
+            episodes: children(channel, "item")
+                .filter_map(|item| {
+                    let enclosure = child(item, "enclosure")?.attribute("url")?.to_owned();
+                    Some(ParsedEpisode {
+                        guid: child_text(item, "guid").unwrap_or_else(|| enclosure.clone()),
+                        title: child_text(item, "title").unwrap_or_default(),
+                        published: child_text(item, "pubDate"),
+                        notes: strip_html(
+                            &child_text(item, (CONTENT_NS, "encoded"))
+                                .or_else(|| child_text(item, "description"))
+                                .unwrap_or_default(),
+                        ),
+                        enclosure,
+                    })
+                })
+                .collect(),
+        })
+    } else if root.has_tag_name((ATOM_NS, "feed")) {
+        // Atom
+        Ok(ParsedFeed {
+            title: child_text(root, (ATOM_NS, "title")).unwrap_or_default(),
+            episodes: children(root, (ATOM_NS, "entry"))
+                .filter_map(|entry| {
+                    let enclosure = children(entry, (ATOM_NS, "link"))
+                        .find(|link| link.attribute("rel") == Some("enclosure"))?
+                        .attribute("href")?
+                        .to_owned();
+                    Some(ParsedEpisode {
+                        guid: child_text(entry, (ATOM_NS, "id"))
+                            .unwrap_or_else(|| enclosure.clone()),
+                        title: child_text(entry, (ATOM_NS, "title")).unwrap_or_default(),
+                        published: child_text(entry, (ATOM_NS, "published"))
+                            .or_else(|| child_text(entry, (ATOM_NS, "updated"))),
+                        notes: strip_html(
+                            &child_text(entry, (ATOM_NS, "content"))
+                                .or_else(|| child_text(entry, (ATOM_NS, "summary")))
+                                .unwrap_or_default(),
+                        ),
+                        enclosure,
+                    })
+                })
+                .collect(),
+        })
+    } else {
+        anyhow::bail!(
+            "Not an RSS or Atom feed (root element is <{}>)",
+            root.tag_name().name()
+        )
+    }
+}
+
+/// Child elements with the given name.
+///
+/// A name without a namespace matches elements of any namespace.
+fn children<'a, 'input, 'n, 'm>(
+    node: Node<'a, 'input>,
+    name: impl Into<ExpandedName<'n, 'm>>,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    let name = name.into();
+    node.children()
+        .filter(move |child| child.has_tag_name(name))
+}
+
+fn child<'a, 'input, 'n, 'm>(
+    node: Node<'a, 'input>,
+    name: impl Into<ExpandedName<'n, 'm>>,
+) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// All the text inside the child element, trimmed. `None` if there's no text.
+fn child_text<'n, 'm>(node: Node, name: impl Into<ExpandedName<'n, 'm>>) -> Option<String> {
+    let text: String = child(node, name)?
+        .descendants()
+        .filter(Node::is_text)
+        .filter_map(|node| node.text())
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// Decode the entities of HTML show notes
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';').filter(|&semi| semi <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..semi];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity.strip_prefix('#').and_then(|num| {
+                let code = match num.strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => num.parse().ok(),
+                };
+                code.and_then(char::from_u32)
+            }),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Turn HTML show notes into plain text
+fn strip_html(html: &str) -> String {
+    let mut out = String::new();
+    let mut rest = html;
+    while let Some(lt) = rest.find('<') {
+        out.push_str(&rest[..lt]);
+        let Some(gt) = rest[lt..].find('>') else {
+            rest = &rest[lt..];
+            break;
+        };
+        let tag = &rest[lt + 1..lt + gt];
+        if ["p", "/p", "br", "br/", "br /", "li"].contains(&tag) {
+            out.push('\n');
+        }
+        rest = &rest[lt + gt + 1..];
+    }
+    out.push_str(rest);
+    let text = unescape(&out);
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join("\n")
+}
+
+#[test]
+fn test_parse_rss_and_atom() {
+    let rss = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd"
+  xmlns:content="http://purl.org/rss/1.0/modules/content/">
+<channel>
+  <title>Frog Talk &amp; Friends</title>
+  <item>
+    <title>Episode 2</title>
+    <pubDate>Tue, 02 Jan 2024 10:00:00 GMT</pubDate>
+    <guid isPermaLink="false">ep2</guid>
+    <description>Short notes</description>
+    <content:encoded><![CDATA[<p>Ribbit</p><p>a &gt; b</p>]]></content:encoded>
+    <enclosure url="http://localhost:8000/ep2.mp3?a=1&amp;b=2" type="audio/mpeg" length="1"/>
+  </item>
+  <item>
+    <title>No audio</title>
+  </item>
+</channel>
+</rss>"#;
+    let feed = parse(rss).unwrap();
+    assert_eq!(feed.title, "Frog Talk & Friends");
+    assert_eq!(feed.episodes.len(), 1);
+    let ep = &feed.episodes[0];
+    assert_eq!(ep.guid, "ep2");
+    assert_eq!(ep.enclosure, "http://localhost:8000/ep2.mp3?a=1&b=2");
+    assert_eq!(ep.notes, "Ribbit\na > b");
+    let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Atom cast</title>
+  <entry>
+    <id>urn:1</id>
+    <title>First</title>
+    <updated>2024-01-01T00:00:00Z</updated>
+    <link rel="alternate" href="http://example.com/1"/>
+    <link rel="enclosure" href="http://example.com/1.ogg"/>
+  </entry>
+</feed>"#;
+    let feed = parse(atom).unwrap();
+    assert_eq!(feed.title, "Atom cast");
+    assert_eq!(feed.episodes[0].enclosure, "http://example.com/1.ogg");
+    assert_eq!(
+        feed.episodes[0].published.as_deref(),
+        Some("2024-01-01T00:00:00Z")
+    );
+}
@@ -0,0 +1,131 @@
+//! Practice trainer: loops an A-B section, speeding up after a number of repetitions
+
+use {
+    crate::config::TrainerConfig,
+    std::time::{Duration, Instant},
+};
+
+/// Longest time between updates that's taken into account when detecting wrap-arounds.
+///
+/// Longer gaps (e.g. while paused) would make any backward seek look like one.
+const MAX_UPDATE_GAP: Duration = Duration::from_millis(500);
+/// Slack for the position reported by mpv lagging behind, in seconds
+const POS_TOLERANCE: f64 = 0.1;
+
+pub struct Trainer {
+    pub cfg: TrainerConfig,
+    /// Current playback speed
+    pub speed: f64,
+    /// Repetitions done at the current speed
+    pub reps_at_speed: u32,
+    pub total_reps: u32,
+    /// Position and time of the last update, to detect the loop wrapping around
+    last: Option<(f64, Instant)>,
+    /// Playback is paused until this point between repetitions
+    pub count_in_until: Option<Instant>,
+    /// Speed before the trainer was started, restored when it's stopped
+    pub prev_speed: f64,
+}
+
+impl Trainer {
+    pub fn new(cfg: TrainerConfig, prev_speed: f64) -> Self {
+        Self {
+            speed: cfg.start_speed,
+            cfg,
+            reps_at_speed: 0,
+            total_reps: 0,
+            last: None,
+            count_in_until: None,
+            prev_speed,
+        }
+    }
+    /// Feed the playback position at `now`. Returns whether a repetition was completed.
+    ///
+    /// The speed is increased if enough repetitions were done at the current one.
+    pub fn advance(&mut self, pos: f64, a: f64, b: f64, now: Instant) -> bool {
+        // mpv jumps back to A when reaching B. Playback must have been able to reach B since
+        // the last update, otherwise it was a seek.
+        let wrapped = self.last.is_some_and(|(last_pos, last_time)| {
+            let elapsed = now.duration_since(last_time).min(MAX_UPDATE_GAP);
+            let step = elapsed.as_secs_f64() * self.speed + POS_TOLERANCE;
+            pos < last_pos && b - last_pos <= step && pos - a <= step
+        });
+        self.last = Some((pos, now));
+        if !wrapped {
+            return false;
+        }
+        self.total_reps += 1;
+        self.reps_at_speed += 1;
+        if self.reps_at_speed >= self.cfg.reps_per_step && !self.reached_target() {
+            self.speed = (self.speed + self.cfg.step).min(self.cfg.target_speed);
+            self.reps_at_speed = 0;
+        }
+        true
+    }
+    pub fn reached_target(&self) -> bool {
+        self.speed >= self.cfg.target_speed
+    }
+    /// How far along the speed is from the start to the target speed, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        let range = self.cfg.target_speed - self.cfg.start_speed;
+        if range <= 0.0 {
+            return 1.0;
+        }
+        ((self.speed - self.cfg.start_speed) / range).clamp(0.0, 1.0) as f32
+    }
+}
+
+#[test]
+fn test_speed_up_after_reps() {
+    let cfg = TrainerConfig {
+        start_speed: 0.7,
+        target_speed: 0.8,
+        step: 0.05,
+        reps_per_step: 2,
+        count_in: 0.0,
+    };
+    let mut trainer = Trainer::new(cfg, 1.0);
+    let mut now = Instant::now();
+    let mut rep = |trainer: &mut Trainer| {
+        assert!(!trainer.advance(15.0, 10.0, 20.0, now));
+        now += Duration::from_millis(100);
+        assert!(!trainer.advance(19.95, 10.0, 20.0, now));
+        now += Duration::from_millis(100);
+        trainer.advance(10.02, 10.0, 20.0, now)
+    };
+    assert!(rep(&mut trainer));
+    assert_eq!(trainer.speed, 0.7);
+    assert!(rep(&mut trainer));
+    assert!((trainer.speed - 0.75).abs() < 1e-9);
+    rep(&mut trainer);
+    rep(&mut trainer);
+    rep(&mut trainer);
+    rep(&mut trainer);
+    assert!(trainer.reached_target());
+    assert_eq!(trainer.speed, 0.8);
+    assert_eq!(trainer.total_reps, 6);
+}
+
+#[test]
+fn test_seeks_are_not_reps() {
+    let cfg = TrainerConfig {
+        start_speed: 1.0,
+        target_speed: 1.0,
+        step: 0.05,
+        reps_per_step: 2,
+        count_in: 0.0,
+    };
+    let mut trainer = Trainer::new(cfg, 1.0);
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+    // A short section of 1.5 s, seeking back from the middle
+    assert!(!trainer.advance(11.0, 10.0, 11.5, at(0)));
+    assert!(!trainer.advance(10.1, 10.0, 11.5, at(20)));
+    // Seeking back after a long pause
+    assert!(!trainer.advance(10.8, 10.0, 11.5, at(40)));
+    assert!(!trainer.advance(10.0, 10.0, 11.5, at(5000)));
+    // The actual wrap-around
+    assert!(!trainer.advance(11.48, 10.0, 11.5, at(5020)));
+    assert!(trainer.advance(10.01, 10.0, 11.5, at(5040)));
+    assert_eq!(trainer.total_reps, 1);
+}
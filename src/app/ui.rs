@@ -1,29 +1,55 @@
 mod color_theme_window;
 mod custom_demuxers_window;
+mod effects_window;
+mod mixer_window;
 mod mpv_console_window;
+mod podcasts_window;
+mod stations_window;
+mod tracks_window;
 
 use {
     self::custom_demuxers_window::CustomDemuxersWindow,
-    super::{Core, LOG, ModalPopup, PlaylistBehavior},
+    super::{
+        Core, LOG, ModalPopup, PlaylistBehavior,
+        cover_art::CoverTexture,
+        loops::LoopSection,
+        lyrics::{self, Lyrics},
+        playlist::{Item, archive},
+    },
     crate::{
-        ipc::Bridge,
+        config::ReplayGainMode,
+        effects::{PitchShift, TempoEngine},
+        ipc::{
+            Bridge, PlaybackState,
+            chapter_list::Chapter,
+            property::{self, Property as _},
+        },
         mpv_handler::ActivePtyInput,
         time_fmt::FfmpegTimeFmt,
         util::{
-            bool_ext::BoolExt as _, egui_ext::EguiResponseExt as _,
-            result_ext::ResultModalExt as _, str_ext::StrExt as _,
+            bool_ext::BoolExt as _, egui_ext::EguiResponseExt as _, result_ext::ResultModalExt as _,
         },
     },
     anyhow::Context as _,
     color_theme_window::ColorThemeWindow,
+    effects_window::EffectsWindow,
     egui_colors::{Colorix, tokens::ThemeColor},
     egui_sf2g::egui::{
-        self, Align, Button, CentralPanel, ComboBox, Context, ScrollArea, TextEdit, TopBottomPanel,
+        self, Align, Button, CentralPanel, Color32, ComboBox, Context, RichText, ScrollArea,
+        TextEdit, TopBottomPanel,
         epaint::text::{FontInsert, FontPriority, InsertFontFamily},
     },
     fuzzy_matcher::{FuzzyMatcher as _, skim::SkimMatcherV2},
+    mixer_window::MixerWindow,
     mpv_console_window::MpvConsoleWindow,
-    std::{borrow::Cow, path::Path},
+    podcasts_window::PodcastsWindow,
+    stations_window::StationsWindow,
+    std::{
+        borrow::Cow,
+        ops::RangeInclusive,
+        path::{Path, PathBuf},
+    },
+    tracks_window::TracksWindow,
 };
 
 #[derive(Default)]
@@ -31,13 +57,31 @@ struct Windows {
     custom_demuxers: CustomDemuxersWindow,
     color_theme: ColorThemeWindow,
     mpv_console: MpvConsoleWindow,
+    tracks: TracksWindow,
+    mixer: MixerWindow,
+    effects: EffectsWindow,
+    stations: StationsWindow,
+    podcasts: PodcastsWindow,
 }
 
 impl Windows {
-    fn update(&mut self, core: &mut Core, ctx: &Context, colorix: &mut Option<Colorix>) {
+    /// Returns whether the playlist changed
+    fn update(
+        &mut self,
+        core: &mut Core,
+        ctx: &Context,
+        colorix: &mut Option<Colorix>,
+        modal: &mut ModalPopup,
+    ) -> bool {
         self.custom_demuxers.update(core, ctx);
         self.color_theme.update(core, ctx, colorix);
         self.mpv_console.update(core, ctx);
+        self.tracks.update(core, ctx, modal);
+        self.mixer.update(core, ctx);
+        self.effects.update(core, ctx, modal);
+        let stations_changed = self.stations.update(core, ctx, modal);
+        let podcasts_changed = self.podcasts.update(core, ctx, modal);
+        stations_changed || podcasts_changed
     }
 }
 
@@ -55,10 +99,15 @@ pub struct Ui {
     filtered_entries: Vec<usize>,
     ab_loop_a: f64,
     ab_loop_b: f64,
+    /// Name for saving the current A-B loop as a section
+    loop_name: String,
     /// If `Some`, focus on the playlist item with that index
     pub focus_on: Option<usize>,
     /// Which filtered entry is selected (up and down keys while filter box is focused)
     selected_filtered_entry: Option<usize>,
+    /// The highlighted lyrics line, to scroll to it when it changes
+    lyrics_line: Option<usize>,
+    cover: CoverTexture,
     pub quit_requested: bool,
 }
 
@@ -68,6 +117,7 @@ enum OutputSource {
     Mpv,
     Demuxer,
     Log,
+    Lyrics,
 }
 
 pub const ICO_PREV: &str = "⏮";
@@ -76,6 +126,10 @@ pub const ICO_NEXT: &str = "⏭";
 enum FileDialogOp {
     LoadMusicFolder,
     AddFont,
+    /// Import loop sections for the song at this path
+    ImportLoops(PathBuf),
+    /// Export the loop sections of the song at this path
+    ExportLoops(PathBuf),
 }
 
 impl Ui {
@@ -116,12 +170,25 @@ impl Ui {
                     }
                 }
                 Some(FileDialogOp::LoadMusicFolder) => crate::app::open_folder(core, self, path),
+                Some(FileDialogOp::ImportLoops(song_path)) => {
+                    match core.loops.import(song_path, &path) {
+                        Ok(count) => crate::logln!("Imported {count} loop sections"),
+                        Err(e) => modal.error("Failed to import loop sections", e),
+                    }
+                }
+                Some(FileDialogOp::ExportLoops(song_path)) => {
+                    if let Err(e) = core.loops.export(song_path, &path) {
+                        modal.error("Failed to export loop sections", e);
+                    }
+                }
                 None => eprintln!("BUG: No operation!"),
             }
         }
         TopBottomPanel::top("top_panel").show(ctx, |ui| self.top_panel_ui(core, ui, modal));
         CentralPanel::default().show(ctx, |ui| self.central_panel_ui(core, ui, modal));
-        self.windows.update(core, ctx, &mut self.colorix);
+        if self.windows.update(core, ctx, &mut self.colorix, modal) {
+            self.recalc_filt_entries(core);
+        }
     }
     fn top_panel_ui(&mut self, core: &mut Core, ui: &mut egui::Ui, modal: &mut ModalPopup) {
         ui.horizontal_centered(|ui| {
@@ -140,10 +207,70 @@ impl Ui {
                 if ui.button("💎 Color theme config").clicked() {
                     self.windows.color_theme.open ^= true;
                 }
+                ui.menu_button("⏱ Fades", |ui| {
+                    let fades = &mut core.cfg.fade;
+                    for (label, ms) in [
+                        ("Pause/resume", &mut fades.pause_resume),
+                        ("Stop", &mut fades.stop),
+                        ("Track change", &mut fades.track_change),
+                        ("Crossfade", &mut fades.crossfade),
+                    ] {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            ui.add(egui::DragValue::new(ms).range(0..=10_000).suffix(" ms"));
+                        });
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Fade durations. 0 means no fade.\n\
+                     Crossfade overlaps the end of a song with the start of the next one.",
+                );
+                ui.menu_button("🔊 ReplayGain", |ui| replaygain_menu_ui(ui, core))
+                    .response
+                    .on_hover_text("Loudness normalization");
+                ui.horizontal(|ui| {
+                    ui.label("Max failures in a row");
+                    ui.add(
+                        egui::DragValue::new(&mut core.cfg.max_consecutive_failures).range(1..=100),
+                    );
+                })
+                .response
+                .on_hover_text("Stop playback after this many files in a row failed to play");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut core.cfg.auto_recover, "Recover from crashes");
+                    ui.add_enabled(
+                        core.cfg.auto_recover,
+                        egui::DragValue::new(&mut core.cfg.max_recovery_attempts)
+                            .range(1..=100)
+                            .suffix(" attempts"),
+                    );
+                })
+                .response
+                .on_hover_text(
+                    "Relaunch the song at the last position if mpv or the demuxer crashes",
+                );
                 ui.checkbox(&mut core.cfg.follow_symlinks, "Follow symlinks")
                     .on_hover_text("Follow symbolic links when reading a directory");
                 ui.checkbox(&mut core.cfg.skip_hidden, "Skip hidden entries")
                     .on_hover_text("Skip hidden files/directories");
+                ui.checkbox(&mut core.cfg.scan_archives, "Scan archives")
+                    .on_hover_text("List the songs inside zip/7z/rar archives (needs 7z)");
+                if ui.button("📻 Stations").clicked() {
+                    self.windows.stations.open ^= true;
+                }
+                if ui.button("🎙 Podcasts").clicked() {
+                    self.windows.podcasts.open ^= true;
+                }
+                ui.menu_button("📖 Audiobooks", |ui| audiobook_menu_ui(ui, core))
+                    .response
+                    .on_hover_text("Folders played as audiobooks, with a bookmark per book");
+                if ui.button("🎚 Effects").clicked() {
+                    self.windows.effects.open ^= true;
+                }
+                if ui.button("🎛 Mixer").clicked() {
+                    self.windows.mixer.open ^= true;
+                }
                 if ui.button("🖳 Mpv console").clicked() {
                     self.windows.mpv_console.open ^= true;
                 }
@@ -223,7 +350,7 @@ impl Ui {
                 && re.lost_focus()
                 && ui.input(|inp| inp.key_pressed(egui::Key::Enter))
             {
-                core.play_selected_song(modal);
+                core.switch_to_selected_song(modal);
             }
             if ctrl_f {
                 re.request_focus();
@@ -253,11 +380,9 @@ impl Ui {
             .iter()
             .enumerate()
             .filter_map(|(idx, item)| {
-                item.path.to_str().and_then(|path_str| {
-                    matcher
-                        .fuzzy_match(path_str, &prepared_filter)
-                        .map(|score| (idx, score))
-                })
+                matcher
+                    .fuzzy_match(&item.display_name(), &prepared_filter)
+                    .map(|score| (idx, score))
             })
             .collect();
         scored_indices.sort_by(|(_, score1), (_, score2)| score1.cmp(score2).reverse());
@@ -279,27 +404,42 @@ impl Ui {
                     ui.label(format!("<No results> ({not_shown_count} not shown)"));
                 }
                 for &i in &self.filtered_entries[range] {
-                    let path = &core.playlist.get(i).unwrap().path;
-                    let re =
-                        ui.selectable_label(core.selected_song == i, path.display().to_string());
-                    re.context_menu(|ui| {
-                        if ui.button("Mix with current").clicked() {
-                            let full_path = core.cfg.music_folder.as_ref().unwrap().join(path);
-                            core.mpv_handler
-                                .ipc(|b| b.add_audio(full_path.as_os_str().to_str().unwrap()))
-                                .err_popup("Failed to add track", modal);
-                            // FIXME: Due to a bug(?) in mpv/libavfilter, more often than not
-                            // there is a desync unless we seek to 0 first.
-                            core.mpv_handler.ipc(|br| br.seek(0.));
-                            crate::logln!(
-                                "Note: There might be desync when seeking with mixed tracks"
-                            );
+                    let item = core.playlist.get(i).unwrap();
+                    // Full path, or URL of streams, archive entries and episodes.
+                    // `None` if the item can't be located, so it can't be mixed either.
+                    let folder = core.cfg.music_folder.as_deref();
+                    let location = match item {
+                        Item::File(path) | Item::CueTrack { path, .. } => {
+                            folder.map(|folder| folder.join(path).to_string_lossy().into_owned())
                         }
-                        if ui.button("Copy full path").clicked() {
-                            let full_path = core.cfg.music_folder.as_ref().unwrap().join(path);
-                            ui.ctx().copy_text(full_path.to_string_lossy().into_owned());
+                        Item::ArchiveEntry { archive, entry } => {
+                            folder.map(|folder| archive::mpv_url(&folder.join(archive), entry))
                         }
-                    });
+                        Item::Url { url, .. } => Some(url.clone()),
+                        Item::Episode { location, .. } => {
+                            (!location.is_empty()).then(|| location.clone())
+                        }
+                    };
+                    let re = ui.selectable_label(core.selected_song == i, item.display_name());
+                    if let Some(location) = location {
+                        re.context_menu(|ui| {
+                            if ui.button("Mix with current").clicked() {
+                                core.mpv_handler
+                                    .ipc(|b| b.add_audio(&location))
+                                    .err_popup("Failed to add track", modal);
+                                self.windows.mixer.mix_added_track();
+                                // FIXME: Due to a bug(?) in mpv/libavfilter, more often than not
+                                // there is a desync unless we seek to 0 first.
+                                core.mpv_handler.ipc(|br| br.seek(0.));
+                                crate::logln!(
+                                    "Note: There might be desync when seeking with mixed tracks"
+                                );
+                            }
+                            if ui.button("Copy full path").clicked() {
+                                ui.ctx().copy_text(location.clone());
+                            }
+                        });
+                    }
                     let filter_changed = self.filter_changed.take();
                     if filter_changed {
                         ui.scroll_to_rect(egui::Rect::ZERO, Some(Align::TOP));
@@ -313,7 +453,7 @@ impl Ui {
                     }
                     if re.clicked() {
                         core.selected_song = i;
-                        core.play_selected_song(modal);
+                        core.switch_to_selected_song(modal);
                         break;
                     }
                 }
@@ -330,35 +470,54 @@ impl Ui {
         }
         ui.separator();
         ui.horizontal(|ui| {
+            self.cover_ui(ui, core);
             ui.group(|ui| {
-                if ui.add(Button::new(ICO_PREV)).clicked() {
+                let in_book = core.current_book().is_some();
+                let skip = core.cfg.audiobook.skip_seconds;
+                if in_book {
+                    if ui
+                        .add(Button::new(format!("⟲ {skip}s")))
+                        .on_hover_text("Skip back")
+                        .clicked()
+                    {
+                        core.seek_relative(-skip).err_popup("Seek error", modal);
+                    }
+                } else if ui.add(Button::new(ICO_PREV)).clicked() {
                     core.play_prev(modal);
                 }
                 let active = core.mpv_handler.active();
-                let icon = if active && !core.mpv_handler.paused() {
-                    "⏸"
-                } else {
-                    "▶"
-                };
-                if ui.add(Button::new(icon)).clicked() {
-                    if active {
-                        core.mpv_handler
-                            .ipc(Bridge::toggle_pause)
-                            .err_popup("Toggle pause error", modal);
-                    } else {
-                        core.play_selected_song(modal);
-                    }
+                let state = core.mpv_handler.playback_state();
+                let icon = if state.is_playing() { "⏸" } else { "▶" };
+                if ui
+                    .add(Button::new(icon))
+                    .on_hover_text(state.label())
+                    .clicked()
+                {
+                    core.play_or_toggle_pause(modal);
                 }
                 if ui.add_enabled(active, Button::new("⏹")).clicked() {
-                    core.stop_music();
+                    core.stop_music(modal);
                 }
-                if ui.add(Button::new(ICO_NEXT)).clicked() {
+                if in_book {
+                    if ui
+                        .add(Button::new(format!("{skip}s ⟳")))
+                        .on_hover_text("Skip forward")
+                        .clicked()
+                    {
+                        core.seek_relative(skip).err_popup("Seek error", modal);
+                    }
+                    book_nav_ui(ui, core, modal);
+                } else if ui.add(Button::new(ICO_NEXT)).clicked() {
                     core.play_next(modal);
                 }
+                chapter_nav_ui(ui, core, modal);
+                if matches!(state, PlaybackState::Buffering | PlaybackState::Seeking) {
+                    ui.label(state.label());
+                }
             });
             ui.group(|ui| {
                 ui.label("🔈");
-                match core.mpv_handler.ipc(|b| b.observed.volume) {
+                match core.mpv_handler.ipc(|b| b.volume()).flatten() {
                     Some(mut vol) => {
                         ui.style_mut().spacing.slider_width = 160.0;
                         let re = ui.add(egui::Slider::new(&mut vol, 0..=150));
@@ -374,8 +533,13 @@ impl Ui {
                 }
             });
             ui.group(|ui| {
-                ui.label("⏩");
-                match core.mpv_handler.ipc(|b| b.observed.speed) {
+                ui.label("⏩")
+                    .on_hover_text("Tempo (the pitch is preserved)");
+                match core
+                    .mpv_handler
+                    .ipc(|b| b.observed.get::<property::Speed>())
+                    .flatten()
+                {
                     Some(mut speed) => {
                         ui.style_mut().spacing.slider_width = 160.0;
                         let re = ui.add(egui::Slider::new(&mut speed, 0.3..=2.0));
@@ -389,6 +553,43 @@ impl Ui {
                         ui.add(egui::Slider::new(&mut core.cfg.speed, 0.3..=2.0));
                     }
                 }
+                let engine_before = core.cfg.tempo_engine;
+                ComboBox::new("tempo_engine_combo", "")
+                    .selected_text(core.cfg.tempo_engine.label())
+                    .show_ui(ui, |ui| {
+                        for engine in [TempoEngine::ScaleTempo2, TempoEngine::Rubberband] {
+                            ui.selectable_value(&mut core.cfg.tempo_engine, engine, engine.label());
+                        }
+                    });
+                if core.cfg.tempo_engine != engine_before {
+                    core.apply_audio_filters(modal);
+                }
+            });
+            ui.group(|ui| {
+                ui.label("🎵")
+                    .on_hover_text("Pitch shift in semitones and cents (uses rubberband)");
+                let pitch_before = core.cfg.pitch;
+                let pitch = &mut core.cfg.pitch;
+                ui.add(
+                    egui::DragValue::new(&mut pitch.semitones)
+                        .range(-12..=12)
+                        .suffix(" st"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut pitch.cents)
+                        .range(-99..=99)
+                        .suffix(" ct"),
+                );
+                if ui
+                    .add_enabled(!pitch.is_zero(), Button::new("⟲"))
+                    .on_hover_text("Reset pitch")
+                    .clicked()
+                {
+                    *pitch = PitchShift::default();
+                }
+                if core.cfg.pitch != pitch_before {
+                    core.apply_audio_filters(modal);
+                }
             });
             if ui.checkbox(&mut core.cfg.video, "video").clicked() {
                 core.mpv_handler
@@ -396,13 +597,18 @@ impl Ui {
                     .err_popup("Video set error", modal);
             }
         });
+        if let Some(title) = core.stream_title() {
+            ui.label(format!("📻 {title}"));
+        }
         ui.horizontal(|ui| {
             if let Some(mut info) = core.mpv_handler.time_info() {
                 ui.style_mut().spacing.slider_width = ui.available_width() - 160.0;
+                // CUE tracks only take up a part of the file
+                let (start, end) = core.song_bounds(info.duration);
                 let re = ui.label(format!(
                     "{}/{}",
-                    FfmpegTimeFmt(info.pos),
-                    FfmpegTimeFmt(info.duration)
+                    FfmpegTimeFmt((info.pos - start).max(0.0)),
+                    FfmpegTimeFmt(end - start)
                 ));
                 re.context_menu(|ui| {
                     ui.menu_button("A-B loop", |ui| {
@@ -453,17 +659,33 @@ impl Ui {
                                 FfmpegTimeFmt(b)
                             ));
                         }
+                        if let Some(song_path) = core.selected_song_path() {
+                            ui.separator();
+                            self.loop_sections_ui(ui, core, modal, song_path);
+                        }
+                        ui.separator();
+                        trainer_menu_ui(ui, core, modal);
                     });
                 });
                 let mut re = ui.add(
-                    egui::Slider::new(&mut info.pos, 0.0..=info.duration)
+                    egui::Slider::new(&mut info.pos, start..=end)
                         .show_value(false)
                         .trailing_fill(true),
                 );
+                if let Some(song_path) = core.selected_song_path() {
+                    paint_loop_markers(
+                        ui,
+                        re.rect,
+                        start..=end,
+                        core.loops.get(&song_path),
+                        core.mpv_handler.ab_loop(),
+                    );
+                }
+                paint_chapter_ticks(ui, re.rect, start..=end, &core.mpv_handler.chapters().0);
                 if let Some(ratio) = re.h_pointer_ratio() {
                     // TODO: This is not 100% accurate, unfortunately
                     re = re.on_hover_text_at_pointer(
-                        FfmpegTimeFmt(info.duration * f64::from(ratio)).to_string(),
+                        FfmpegTimeFmt((end - start) * f64::from(ratio)).to_string(),
                     );
                 }
                 if re.drag_stopped() {
@@ -471,6 +693,9 @@ impl Ui {
                 }
             }
         });
+        if core.trainer.is_some() {
+            trainer_status_ui(ui, core, modal);
+        }
         ui.separator();
         ui.horizontal(|ui| {
             let re = ui.selectable_value(&mut self.output_source, OutputSource::Mpv, "Mpv");
@@ -486,11 +711,14 @@ impl Ui {
             if !core.mpv_handler.demuxer_active() && core.mpv_handler.demux_term.is_empty() {
                 demux_enabled = false;
             }
-            let tab_name = if core.mpv_handler.demux_cmd_name.is_empty() {
+            let mut tab_name = if core.mpv_handler.demux_cmd_name.is_empty() {
                 Cow::Borrowed("Demuxer")
             } else {
                 Cow::Owned(format!("Demuxer: {}", core.mpv_handler.demux_cmd_name))
             };
+            if let Some(status) = core.mpv_handler.demux_exit_status {
+                tab_name = Cow::Owned(format!("{tab_name} ({status})"));
+            }
             let re = ui.add_enabled(
                 demux_enabled,
                 Button::selectable(self.output_source == OutputSource::Demuxer, tab_name),
@@ -505,15 +733,61 @@ impl Ui {
                 core.mpv_handler.active_pty_input = ActivePtyInput::Demuxer;
             };
             ui.selectable_value(&mut self.output_source, OutputSource::Log, "Log");
+            let re = ui.add_enabled(
+                core.lyrics.is_some(),
+                Button::selectable(self.output_source == OutputSource::Lyrics, "Lyrics"),
+            );
+            if let Some(lyrics) = &core.lyrics {
+                let source = match &lyrics.source {
+                    lyrics::Source::File(path) => path.display().to_string(),
+                    lyrics::Source::Embedded => "Embedded in the tags".to_owned(),
+                };
+                if re.on_hover_text(source).clicked() {
+                    self.output_source = OutputSource::Lyrics;
+                    // Scroll to the current line
+                    self.lyrics_line = None;
+                }
+            } else {
+                re.on_disabled_hover_text("No lyrics found");
+            }
             ui.separator();
-            if let Some(track_count) = core.mpv_handler.ipc(|b| b.observed.track_count) {
-                let s = if track_count == 1 { "" } else { "s" };
-                ui.label(format!("{track_count} active track{s}"));
+            if !core.failed_songs.is_empty() {
+                let n = core.failed_songs.len();
+                let s = if n == 1 { "" } else { "s" };
+                let mut hover_text = String::new();
+                for failed in &core.failed_songs {
+                    hover_text.push_str(&format!("{}: {}\n", failed.path.display(), failed.reason));
+                }
+                hover_text.push_str("\nClick to dismiss");
+                if ui
+                    .button(RichText::new(format!("⚠ {n} file{s} failed")).color(Color32::YELLOW))
+                    .on_hover_text(hover_text)
+                    .clicked()
+                {
+                    core.failed_songs.clear();
+                }
+                ui.separator();
             }
-            if let Some([playlist_count, playlist_pos]) = core
+            if let Some(track_count) = core
                 .mpv_handler
-                .ipc(|b| [b.observed.playlist_count, b.observed.playlist_pos])
-                && playlist_count > 1
+                .ipc(|b| b.observed.get::<property::TrackListCount>())
+                .flatten()
+            {
+                let s = if track_count == 1 { "" } else { "s" };
+                if ui
+                    .button(format!("{track_count} active track{s}"))
+                    .on_hover_text("Show tracks")
+                    .clicked()
+                {
+                    self.windows.tracks.open ^= true;
+                }
+            }
+            if let Some(Some([playlist_count, playlist_pos])) = core.mpv_handler.ipc(|b| {
+                Some([
+                    b.observed.get::<property::PlaylistCount>()?,
+                    b.observed.get::<property::PlaylistPos>()?,
+                ])
+            }) && playlist_count > 1
             {
                 ui.separator();
                 let pos = playlist_pos + 1;
@@ -525,7 +799,11 @@ impl Ui {
                     core.mpv_handler.ipc(Bridge::playlist_next);
                 }
             }
-            if let Some(complex) = core.mpv_handler.ipc(|b| b.observed.lavfi_complex.as_str()) {
+            if let Some(Some(complex)) = core.mpv_handler.ipc(|b| {
+                b.observed
+                    .get_raw(property::LavfiComplex::NAME)
+                    .and_then(serde_json::Value::as_str)
+            }) {
                 let mut remove = false;
                 if !complex.is_empty() {
                     ui.menu_button("lavfi-complex filter active", |ui| {
@@ -539,12 +817,21 @@ impl Ui {
                     core.mpv_handler.ipc(|b| b.switch_to_track(1));
                 }
             }
-            if let Some(mut loop_file) = core.mpv_handler.ipc(|b| b.observed.loop_file) {
+            if let Some(mut loop_file) = core.mpv_handler.ipc(|b| b.loop_file()) {
                 if ui.checkbox(&mut loop_file, "loop").clicked() {
                     core.mpv_handler.ipc(|b| b.set_loop_file(loop_file));
                 }
             }
         });
+        if self.output_source == OutputSource::Lyrics
+            && let Some(lyrics) = &core.lyrics
+        {
+            let pos = core.mpv_handler.time_info().map(|info| info.pos);
+            if let Some(time) = self.lyrics_ui(ui, lyrics, pos) {
+                core.seek(time).err_popup("Seek error", modal);
+            }
+            return;
+        }
         ScrollArea::vertical()
             .auto_shrink([false; 2])
             .id_salt("out_scroll")
@@ -554,41 +841,13 @@ impl Ui {
                     OutputSource::Mpv => core.mpv_handler.mpv_output(),
                     OutputSource::Demuxer => core.mpv_handler.demux_term.contents_to_string(),
                     OutputSource::Log => LOG.lock().unwrap().clone(),
+                    // The song changed to one without lyrics
+                    OutputSource::Lyrics => String::new(),
                 };
                 let out = TextEdit::multiline(&mut out.as_str())
                     .desired_width(f32::INFINITY)
                     .font(egui::TextStyle::Monospace)
                     .show(ui);
-                if out.response.clicked() {
-                    if let Some(range) = out.cursor_range {
-                        let l_cur = out.galley.layout_from_cursor(range.primary);
-                        let row = l_cur.row;
-                        // The little audio "handles" are at the beginning of lines,
-                        // so let's ignore the later columns, so the user doesn't accidentally
-                        // switch tracks when clicking lines that contain --aid bits
-                        if l_cur.column > 18 {
-                            return;
-                        }
-                        if let Some(line) = core.mpv_handler.mpv_output().lines().nth(row) {
-                            if let Some(range) = line.find_token_after("--aid=") {
-                                let track_num: u64 = line[range].parse().unwrap();
-                                let [ctrl, shift] =
-                                    ui.input(|inp| [inp.modifiers.ctrl, inp.modifiers.shift]);
-                                if shift {
-                                    core.mpv_handler.ipc(|b| b.mix_t1_with_track(track_num));
-                                } else if ctrl {
-                                    if let Some(Err(e)) =
-                                        core.mpv_handler.ipc(|b| b.remove_track(track_num))
-                                    {
-                                        modal.error("Error removing track", e);
-                                    }
-                                } else {
-                                    core.mpv_handler.ipc(|b| b.switch_to_track(track_num));
-                                }
-                            }
-                        }
-                    }
-                }
                 // Weird hack to make PTY interaction work even if the TextEdit was clicked.
                 // Normally, the `TextEdit` is interested in keyboard events even in the
                 // "immutable" mode, which is not what we want.
@@ -602,6 +861,138 @@ impl Ui {
                 }
             });
     }
+    fn cover_ui(&mut self, ui: &mut egui::Ui, core: &Core) {
+        let song = core.selected_song_path();
+        let Some(texture) = self.cover.get(ui.ctx(), &core.covers, song.as_deref()) else {
+            return;
+        };
+        let re = ui.add(
+            egui::Image::new(texture)
+                .max_size(egui::vec2(48.0, 48.0))
+                .sense(egui::Sense::click()),
+        );
+        if let Some(art) = core.cover_art() {
+            let url = art.art_url();
+            re.on_hover_ui(|ui| {
+                ui.add(egui::Image::new(texture).max_size(egui::vec2(256.0, 256.0)));
+            })
+            .context_menu(|ui| {
+                if ui.button("Copy cover art URL").clicked() {
+                    ui.ctx().copy_text(url.clone());
+                    ui.close();
+                }
+            });
+        }
+    }
+    /// Returns the time of the clicked line, to seek to
+    fn lyrics_ui(&mut self, ui: &mut egui::Ui, lyrics: &Lyrics, pos: Option<f64>) -> Option<f64> {
+        let current = pos.and_then(|pos| lyrics.current_line(pos));
+        let scroll = current != self.lyrics_line;
+        self.lyrics_line = current;
+        let mut seek_to = None;
+        ScrollArea::vertical()
+            .auto_shrink([false; 2])
+            .id_salt("lyrics_scroll")
+            .show(ui, |ui| {
+                for (i, line) in lyrics.lines.iter().enumerate() {
+                    let Some(time) = line.time else {
+                        ui.label(&line.text);
+                        continue;
+                    };
+                    let re = ui
+                        .selectable_label(current == Some(i), &line.text)
+                        .on_hover_text(format!("Seek to {}", FfmpegTimeFmt(time)));
+                    if re.clicked() {
+                        seek_to = Some(time);
+                    }
+                    if scroll && current == Some(i) {
+                        re.scroll_to_me(Some(Align::Center));
+                    }
+                }
+            });
+        seek_to
+    }
+    fn loop_sections_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        core: &mut Core,
+        modal: &mut ModalPopup,
+        song_path: PathBuf,
+    ) {
+        let active = core.mpv_handler.ab_loop();
+        let mut remove = None;
+        for (i, section) in core.loops.get(&song_path).iter().enumerate() {
+            ui.horizontal(|ui| {
+                let is_active = active == Some((Some(section.a), Some(section.b)));
+                let text = format!(
+                    "{} ({}-{})",
+                    section.name,
+                    FfmpegTimeFmt(section.a),
+                    FfmpegTimeFmt(section.b)
+                );
+                if ui.selectable_label(is_active, text).clicked() {
+                    self.ab_loop_a = section.a;
+                    self.ab_loop_b = section.b;
+                    core.mpv_handler
+                        .ipc(|b| {
+                            b.set_ab_loop(Some(section.a), Some(section.b))?;
+                            b.seek(section.a)
+                        })
+                        .err_popup("Error setting A-B loop", modal);
+                }
+                if ui.button("🗑").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(idx) = remove {
+            core.loops.remove(&song_path, idx);
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                TextEdit::singleline(&mut self.loop_name)
+                    .hint_text("Section name")
+                    .desired_width(120.0),
+            );
+            let name = self.loop_name.trim();
+            // Save what mpv actually loops, as long as it's a proper section
+            let section = match active {
+                Some((Some(a), Some(b))) if b > a => Some((a, b)),
+                _ => None,
+            };
+            if ui
+                .add_enabled(
+                    !name.is_empty() && section.is_some(),
+                    Button::new("Save A-B"),
+                )
+                .on_disabled_hover_text("Needs a name, and an A-B loop with B after A")
+                .clicked()
+                && let Some((a, b)) = section
+            {
+                core.loops.insert(
+                    &song_path,
+                    LoopSection {
+                        name: name.to_owned(),
+                        a,
+                        b,
+                    },
+                );
+                self.loop_name.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Import...").clicked() {
+                self.file_dialog.pick_file();
+                self.file_dialog
+                    .set_user_data(FileDialogOp::ImportLoops(song_path.clone()));
+            }
+            if ui.button("Export...").clicked() {
+                self.file_dialog.save_file();
+                self.file_dialog
+                    .set_user_data(FileDialogOp::ExportLoops(song_path));
+            }
+        });
+    }
     pub fn apply_colorix_theme(&mut self, theme: Option<&[[u8; 3]; 12]>, ctx: &Context) {
         if let Some(theme) = theme {
             self.colorix = Some(Colorix::global(
@@ -612,6 +1003,366 @@ impl Ui {
     }
 }
 
+fn trainer_menu_ui(ui: &mut egui::Ui, core: &mut Core, modal: &mut ModalPopup) {
+    ui.menu_button("🎓 Practice trainer", |ui| {
+        let cfg = &mut core.cfg.trainer;
+        egui::Grid::new("trainer_grid").show(ui, |ui| {
+            ui.label("Start speed");
+            ui.add(
+                egui::DragValue::new(&mut cfg.start_speed)
+                    .range(0.1..=4.0)
+                    .speed(0.01),
+            );
+            ui.end_row();
+            ui.label("Target speed");
+            ui.add(
+                egui::DragValue::new(&mut cfg.target_speed)
+                    .range(0.1..=4.0)
+                    .speed(0.01),
+            );
+            ui.end_row();
+            ui.label("Step");
+            ui.add(
+                egui::DragValue::new(&mut cfg.step)
+                    .range(0.01..=1.0)
+                    .speed(0.01),
+            );
+            ui.end_row();
+            ui.label("Repetitions per step");
+            ui.add(egui::DragValue::new(&mut cfg.reps_per_step).range(1..=100));
+            ui.end_row();
+            ui.label("Count-in")
+                .on_hover_text("Pause between repetitions");
+            ui.add(
+                egui::DragValue::new(&mut cfg.count_in)
+                    .range(0.0..=10.0)
+                    .speed(0.1)
+                    .suffix(" s"),
+            );
+            ui.end_row();
+        });
+        let has_loop = matches!(core.mpv_handler.ab_loop(), Some((Some(_), Some(_))));
+        if core.trainer.is_some() {
+            if ui.button("Stop").clicked() {
+                core.stop_trainer(modal);
+            }
+        } else if ui
+            .add_enabled(has_loop, Button::new("Start"))
+            .on_disabled_hover_text("Set an A-B loop first")
+            .clicked()
+        {
+            core.start_trainer(modal);
+        }
+    });
+}
+
+fn trainer_status_ui(ui: &mut egui::Ui, core: &mut Core, modal: &mut ModalPopup) {
+    let Some(trainer) = &core.trainer else {
+        return;
+    };
+    let mut stop = false;
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "🎓 {:.0}% → {:.0}%, rep {} ({}/{} at this speed)",
+            trainer.speed * 100.0,
+            trainer.cfg.target_speed * 100.0,
+            trainer.total_reps,
+            trainer.reps_at_speed,
+            trainer.cfg.reps_per_step,
+        ));
+        if let Some(until) = trainer.count_in_until {
+            let left = until.saturating_duration_since(std::time::Instant::now());
+            ui.label(format!("⏳ {:.1}", left.as_secs_f64()));
+        }
+        stop = ui.button("Stop").clicked();
+        let text = if trainer.reached_target() {
+            "Target reached"
+        } else {
+            ""
+        };
+        ui.add(egui::ProgressBar::new(trainer.progress()).text(text));
+    });
+    if stop {
+        core.stop_trainer(modal);
+    }
+}
+
+/// Continue button and sleep timer of the audiobook mode
+fn book_nav_ui(ui: &mut egui::Ui, core: &mut Core, modal: &mut ModalPopup) {
+    let bookmark = core
+        .current_book()
+        .and_then(|book| core.bookmarks.get(book));
+    if let Some(bookmark) = bookmark
+        && !core
+            .playlist
+            .get(core.selected_song)
+            .is_some_and(|item| bookmark.is_in(item))
+        && ui
+            .button("📖 Continue")
+            .on_hover_text(format!(
+                "Continue from {} in {}",
+                FfmpegTimeFmt(bookmark.pos),
+                bookmark.file.display()
+            ))
+            .clicked()
+    {
+        core.continue_book(modal);
+    }
+    let remaining = core
+        .sleep_until
+        .map(|until| until.saturating_duration_since(std::time::Instant::now()));
+    let label = match remaining {
+        Some(left) => format!("🌙 {}", FfmpegTimeFmt(left.as_secs_f64())),
+        None => "🌙".to_owned(),
+    };
+    ui.menu_button(label, |ui| {
+        for minutes in [0u64, 15, 30, 45, 60] {
+            let text = if minutes == 0 {
+                "Off".to_owned()
+            } else {
+                format!("{minutes} min")
+            };
+            if ui.button(text).clicked() {
+                core.sleep_until = (minutes != 0).then(|| {
+                    std::time::Instant::now() + std::time::Duration::from_secs(minutes * 60)
+                });
+                ui.close();
+            }
+        }
+    })
+    .response
+    .on_hover_text("Sleep timer");
+}
+
+fn audiobook_menu_ui(ui: &mut egui::Ui, core: &mut Core) {
+    let cfg = &mut core.cfg.audiobook;
+    let mut remove = None;
+    for (i, folder) in cfg.folders.iter().enumerate() {
+        ui.horizontal(|ui| {
+            if ui.button("🗑").clicked() {
+                remove = Some(i);
+            }
+            ui.label(folder.display().to_string());
+        });
+    }
+    if let Some(idx) = remove {
+        cfg.folders.remove(idx);
+    }
+    let selected_folder = match core.playlist.get(core.selected_song) {
+        Some(Item::File(path)) => path.parent().filter(|dir| !dir.as_os_str().is_empty()),
+        _ => None,
+    };
+    let can_add = selected_folder.is_some_and(|dir| !cfg.folders.iter().any(|f| f == dir));
+    if ui
+        .add_enabled(can_add, Button::new("➕ Add folder of selected song"))
+        .clicked()
+        && let Some(dir) = selected_folder
+    {
+        cfg.folders.push(dir.to_owned());
+    }
+    ui.separator();
+    egui::Grid::new("audiobook_grid").show(ui, |ui| {
+        ui.label("Skip by");
+        ui.add(
+            egui::DragValue::new(&mut cfg.skip_seconds)
+                .range(1.0..=600.0)
+                .suffix(" s"),
+        );
+        ui.end_row();
+        ui.label("Rewind after pauses of");
+        ui.add(
+            egui::DragValue::new(&mut cfg.rewind_after)
+                .range(1..=24 * 60)
+                .suffix(" min"),
+        );
+        ui.end_row();
+        ui.label("Rewind by");
+        ui.add(
+            egui::DragValue::new(&mut cfg.rewind_by)
+                .range(0.0..=600.0)
+                .suffix(" s"),
+        );
+        ui.end_row();
+        ui.label("Sleep timer");
+        ui.add(
+            egui::DragValue::new(&mut cfg.sleep_timer)
+                .range(0..=24 * 60)
+                .suffix(" min"),
+        )
+        .on_hover_text("Started when playing a book. 0 means off.");
+        ui.end_row();
+    });
+}
+
+/// Previous/next chapter buttons, and a dropdown to jump to a chapter
+fn chapter_nav_ui(ui: &mut egui::Ui, core: &mut Core, modal: &mut ModalPopup) {
+    let (chapters, current) = core.mpv_handler.chapters();
+    if chapters.is_empty() {
+        return;
+    }
+    let pos = core.mpv_handler.time_info().map_or(0.0, |info| info.pos);
+    ui.separator();
+    // Like most players, go to the start of the current chapter first, unless near it already
+    let prev = current.and_then(|idx| {
+        if pos - chapters[idx].time > 3.0 {
+            Some(idx)
+        } else {
+            idx.checked_sub(1)
+        }
+    });
+    let next = current.map_or(0, |idx| idx + 1);
+    let mut jump_to = None;
+    if ui
+        .add_enabled(prev.is_some(), Button::new("⏪"))
+        .on_hover_text("Previous chapter")
+        .clicked()
+    {
+        jump_to = prev;
+    }
+    let selected_text = current.map_or_else(
+        || "<no chapter>".to_owned(),
+        |idx| chapters[idx].display_title(idx),
+    );
+    ComboBox::new("chapter_combo", "")
+        .selected_text(selected_text)
+        .width(160.0)
+        .show_ui(ui, |ui| {
+            for (i, chapter) in chapters.iter().enumerate() {
+                let text = format!(
+                    "{} {}",
+                    FfmpegTimeFmt(chapter.time),
+                    chapter.display_title(i)
+                );
+                if ui.selectable_label(current == Some(i), text).clicked() {
+                    jump_to = Some(i);
+                }
+            }
+        });
+    if ui
+        .add_enabled(next < chapters.len(), Button::new("⏩"))
+        .on_hover_text("Next chapter")
+        .clicked()
+    {
+        jump_to = Some(next);
+    }
+    if let Some(idx) = jump_to {
+        core.mpv_handler
+            .ipc(|b| b.set_chapter(idx))
+            .err_popup("Failed to change chapter", modal);
+    }
+}
+
+/// The x coordinate of `pos` on a slider at `rect` covering `range`
+fn slider_x(rect: egui::Rect, range: &RangeInclusive<f64>, pos: f64) -> f32 {
+    let ratio = (pos - range.start()) / (range.end() - range.start());
+    rect.left() + rect.width() * ratio.clamp(0.0, 1.0) as f32
+}
+
+fn paint_chapter_ticks(
+    ui: &egui::Ui,
+    rect: egui::Rect,
+    range: RangeInclusive<f64>,
+    chapters: &[Chapter],
+) {
+    if range.end() <= range.start() {
+        return;
+    }
+    let painter = ui.painter();
+    let stroke = egui::Stroke::new(1.0, ui.visuals().weak_text_color());
+    // The first chapter usually starts at the start, no point marking that
+    for chapter in chapters
+        .iter()
+        .filter(|ch| ch.time > *range.start() && ch.time <= *range.end())
+    {
+        let x = slider_x(rect, &range, chapter.time);
+        painter.vline(x, rect.top()..=rect.top() + 4.0, stroke);
+        painter.vline(x, rect.bottom() - 4.0..=rect.bottom(), stroke);
+    }
+}
+
+/// Mark the saved loop sections on the seek slider
+fn paint_loop_markers(
+    ui: &egui::Ui,
+    rect: egui::Rect,
+    range: RangeInclusive<f64>,
+    sections: &[LoopSection],
+    active: Option<(Option<f64>, Option<f64>)>,
+) {
+    if range.end() <= range.start() {
+        return;
+    }
+    let x_of = |pos: f64| slider_x(rect, &range, pos);
+    let painter = ui.painter();
+    for section in sections {
+        let is_active = active == Some((Some(section.a), Some(section.b)));
+        let color = if is_active {
+            Color32::from_rgb(255, 200, 0)
+        } else {
+            Color32::from_rgb(100, 180, 255)
+        };
+        let (a, b) = (x_of(section.a), x_of(section.b));
+        let band = egui::Rect::from_x_y_ranges(a..=b, rect.bottom() - 3.0..=rect.bottom());
+        painter.rect_filled(band, 0.0, color.gamma_multiply(0.5));
+        for x in [a, b] {
+            painter.vline(x, rect.y_range(), egui::Stroke::new(1.0, color));
+        }
+    }
+}
+
+fn replaygain_menu_ui(ui: &mut egui::Ui, core: &mut Core) {
+    let rg = &mut core.cfg.replaygain;
+    let mut start_analysis = false;
+    ui.horizontal(|ui| {
+        for (mode, label) in [
+            (ReplayGainMode::Off, "Off"),
+            (ReplayGainMode::Track, "Track"),
+            (ReplayGainMode::Album, "Album"),
+        ] {
+            ui.selectable_value(&mut rg.mode, mode, label);
+        }
+    });
+    ui.add_enabled_ui(rg.mode != ReplayGainMode::Off, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Preamp");
+            ui.add(
+                egui::DragValue::new(&mut rg.preamp)
+                    .range(-15.0..=15.0)
+                    .speed(0.1)
+                    .suffix(" dB"),
+            );
+        });
+        ui.checkbox(&mut rg.clip_prevention, "Prevent clipping");
+        let re = ui
+            .checkbox(&mut rg.analyze_untagged, "Analyze untagged files")
+            .on_hover_text(
+                "Measure the loudness of files without ReplayGain tags in the background \
+                 (requires ffmpeg)",
+            );
+        start_analysis = re.clicked() && rg.analyze_untagged;
+        ui.add_enabled_ui(rg.analyze_untagged, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Target loudness");
+                ui.add(
+                    egui::DragValue::new(&mut rg.target_loudness)
+                        .range(-40.0..=-5.0)
+                        .speed(0.1)
+                        .suffix(" LUFS"),
+                );
+            });
+        });
+    });
+    if start_analysis {
+        core.analyze_loudness();
+    }
+    if let Some((done, queued)) = core.loudness.progress() {
+        ui.add(
+            egui::ProgressBar::new(done as f32 / queued as f32)
+                .text(format!("Analyzing {done}/{queued}")),
+        );
+    }
+    ui.label("Changes apply from the next song");
+}
+
 impl PlaylistBehavior {
     fn label(&self) -> &'static str {
         match self {
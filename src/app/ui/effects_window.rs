@@ -0,0 +1,194 @@
+use {
+    crate::{
+        app::{ModalPopup, core::Core},
+        effects::{EQ_BANDS, EffectPreset},
+    },
+    egui_sf2g::egui::{self, ComboBox, Slider},
+};
+
+#[derive(Default)]
+pub struct EffectsWindow {
+    pub open: bool,
+    selected_preset: Option<usize>,
+    preset_name: String,
+}
+
+impl EffectsWindow {
+    pub(crate) fn update(&mut self, core: &mut Core, ctx: &egui::Context, modal: &mut ModalPopup) {
+        let before = core.cfg.effects.clone();
+        let mut open = self.open;
+        egui::Window::new("🎚 Effects")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if let Some(name) = &core.bound_preset {
+                    ui.label(format!(
+                        "Editing preset `{name}`, bound to the current song"
+                    ));
+                }
+                rack_ui(ui, core);
+                ui.separator();
+                self.presets_ui(ui, core);
+            });
+        self.open = open;
+        if core.cfg.effects != before {
+            core.store_bound_effects();
+            core.apply_audio_filters(modal);
+        }
+    }
+    fn presets_ui(&mut self, ui: &mut egui::Ui, core: &mut Core) {
+        let presets = &mut core.cfg.effect_presets;
+        if self.selected_preset.is_some_and(|idx| idx >= presets.len()) {
+            self.selected_preset = None;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Preset");
+            let selected_name = self
+                .selected_preset
+                .map_or("<none>", |idx| presets[idx].name.as_str());
+            ComboBox::new("effect_preset_combo", "")
+                .selected_text(selected_name)
+                .show_ui(ui, |ui| {
+                    for (i, preset) in presets.iter().enumerate() {
+                        if ui
+                            .selectable_label(self.selected_preset == Some(i), &preset.name)
+                            .clicked()
+                        {
+                            self.selected_preset = Some(i);
+                            self.preset_name.clone_from(&preset.name);
+                        }
+                    }
+                });
+            if let Some(idx) = self.selected_preset {
+                if ui.button("Load").clicked() {
+                    core.cfg.effects = presets[idx].rack.clone();
+                }
+                if ui.button("Delete").clicked() {
+                    presets.remove(idx);
+                    self.selected_preset = None;
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.preset_name);
+            let name = self.preset_name.trim();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Save current"))
+                .on_hover_text("Save the current effects as a preset with this name")
+                .clicked()
+            {
+                let rack = core.cfg.effects.clone();
+                match presets.iter().position(|preset| preset.name == name) {
+                    Some(idx) => {
+                        presets[idx].rack = rack;
+                        self.selected_preset = Some(idx);
+                    }
+                    None => {
+                        presets.push(EffectPreset {
+                            name: name.to_owned(),
+                            rack,
+                            ..Default::default()
+                        });
+                        self.selected_preset = Some(presets.len() - 1);
+                    }
+                }
+            }
+        });
+        let Some(idx) = self.selected_preset else {
+            return;
+        };
+        ui.label("Apply automatically to songs in folders:");
+        let preset = &mut presets[idx];
+        preset.folders.retain(|folder| {
+            ui.horizontal(|ui| {
+                ui.label(folder.display().to_string());
+                !ui.button("🗑").clicked()
+            })
+            .inner
+        });
+        let song_folder = core
+            .playlist
+            .get(core.selected_song)
+            .and_then(|item| item.path()?.parent())
+            .filter(|folder| !folder.as_os_str().is_empty());
+        if let Some(folder) = song_folder
+            && !preset.folders.iter().any(|f| f == folder)
+            && ui
+                .button(format!("+ {}", folder.display()))
+                .on_hover_text("Folder of the selected song")
+                .clicked()
+        {
+            preset.folders.push(folder.to_owned());
+        }
+        ui.label("Apply automatically to songs played with demuxers:");
+        for demuxer in &core.cfg.custom_demuxers {
+            if demuxer.name.is_empty() {
+                continue;
+            }
+            let pos = preset
+                .demuxers
+                .iter()
+                .position(|name| *name == demuxer.name);
+            let mut bound = pos.is_some();
+            if ui.checkbox(&mut bound, &demuxer.name).clicked() {
+                match pos {
+                    Some(pos) => {
+                        preset.demuxers.remove(pos);
+                    }
+                    None => preset.demuxers.push(demuxer.name.clone()),
+                }
+            }
+        }
+    }
+}
+
+fn rack_ui(ui: &mut egui::Ui, core: &mut Core) {
+    let rack = &mut core.cfg.effects;
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut rack.equalizer.enabled, "Equalizer");
+        if ui.button("Flat").clicked() {
+            rack.equalizer.gains = [0.0; 10];
+        }
+    });
+    ui.add_enabled_ui(rack.equalizer.enabled, |ui| {
+        ui.horizontal(|ui| {
+            for (freq, gain) in EQ_BANDS.iter().zip(&mut rack.equalizer.gains) {
+                ui.vertical(|ui| {
+                    ui.add(Slider::new(gain, -12.0..=12.0).vertical().step_by(0.5));
+                    let label = if *freq >= 1000 {
+                        format!("{}k", freq / 1000)
+                    } else {
+                        freq.to_string()
+                    };
+                    ui.label(label);
+                });
+            }
+        });
+    });
+    ui.separator();
+    ui.checkbox(&mut rack.tone.enabled, "Bass/treble");
+    ui.add_enabled_ui(rack.tone.enabled, |ui| {
+        ui.add(Slider::new(&mut rack.tone.bass, -12.0..=12.0).text("Bass (dB)"));
+        ui.add(Slider::new(&mut rack.tone.treble, -12.0..=12.0).text("Treble (dB)"));
+    });
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut rack.stereo_width.enabled, "Stereo width");
+        ui.add_enabled(
+            rack.stereo_width.enabled,
+            Slider::new(&mut rack.stereo_width.width, 0.0..=3.0),
+        );
+    });
+    ui.checkbox(&mut rack.mono, "Mono");
+    ui.checkbox(&mut rack.karaoke, "Karaoke")
+        .on_hover_text("Remove the center channel, which usually contains the vocals");
+    ui.separator();
+    ui.checkbox(&mut rack.compressor.enabled, "Compressor");
+    ui.add_enabled_ui(rack.compressor.enabled, |ui| {
+        let c = &mut rack.compressor;
+        ui.add(Slider::new(&mut c.threshold, -60.0..=0.0).text("Threshold (dB)"));
+        ui.add(Slider::new(&mut c.ratio, 1.0..=20.0).text("Ratio"));
+        ui.add(Slider::new(&mut c.attack, 0.1..=200.0).text("Attack (ms)"));
+        ui.add(Slider::new(&mut c.release, 10.0..=2000.0).text("Release (ms)"));
+        ui.add(Slider::new(&mut c.makeup, 0.0..=24.0).text("Makeup (dB)"));
+    });
+}
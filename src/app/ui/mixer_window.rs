@@ -0,0 +1,282 @@
+use {
+    crate::{
+        app::core::Core,
+        ipc::{
+            Request,
+            property::{LavfiComplex, Property as _, TrackList},
+            track_list::{Track, TrackKind},
+        },
+    },
+    egui_sf2g::egui,
+    std::fmt::Write as _,
+};
+
+/// Mixing parameters of one audio track
+#[derive(Clone, PartialEq)]
+struct MixerTrack {
+    id: u64,
+    label: String,
+    /// Linear gain factor
+    volume: f64,
+    mute: bool,
+    solo: bool,
+    /// Stereo balance, from -1 (left) to 1 (right)
+    pan: f64,
+    /// Seconds to delay (positive) or skip (negative) the track by
+    offset: f64,
+}
+
+impl MixerTrack {
+    fn new(track: &Track) -> Self {
+        let name = track
+            .title
+            .as_deref()
+            .or(track.external_filename.as_deref())
+            .unwrap_or_default();
+        Self {
+            id: track.id,
+            label: format!("#{} {name}", track.id),
+            volume: 1.0,
+            mute: false,
+            solo: false,
+            pan: 0.0,
+            offset: 0.0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MixerWindow {
+    pub open: bool,
+    observing: bool,
+    tracks: Vec<MixerTrack>,
+    /// The graph that was last sent to mpv
+    applied_graph: Option<String>,
+    pending: Option<Request<()>>,
+    error: Option<String>,
+    /// Set when a track was added to be mixed in, until mixing starts
+    mix_added: bool,
+}
+
+impl MixerWindow {
+    /// Start mixing in a track that was added with `audio-add`
+    pub(crate) fn mix_added_track(&mut self) {
+        self.open = true;
+        self.mix_added = true;
+    }
+    pub(crate) fn update(&mut self, core: &mut Core, ctx: &egui::Context) {
+        if self.open != self.observing {
+            if self.open {
+                core.mpv_handler.observe(TrackList::NAME);
+            } else {
+                core.mpv_handler.unobserve(TrackList::NAME);
+            }
+            self.observing = self.open;
+        }
+        if !self.open {
+            return;
+        }
+        if let Some(request) = &self.pending
+            && let Some(result) = core.mpv_handler.ipc(|b| b.poll(request)).flatten()
+        {
+            self.error = result.err().map(|e| e.to_string());
+            self.pending = None;
+        }
+        let audio_tracks: Vec<Track> = core
+            .mpv_handler
+            .ipc(|b| b.observed.get::<TrackList>())
+            .flatten()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|t| t.kind == TrackKind::Audio)
+            .collect();
+        // A new file was loaded, nothing to keep mixing
+        if audio_tracks.is_empty() {
+            self.applied_graph = None;
+        }
+        let before = self.tracks.clone();
+        self.sync_tracks(&audio_tracks);
+        let mut disable = false;
+        egui::Window::new("🎛 Mixer")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                if self.tracks.len() < 2 {
+                    ui.label("Add tracks to mix with \"Mix with current\" on a song");
+                    return;
+                }
+                egui::Grid::new("mixer_grid").striped(true).show(ui, |ui| {
+                    for header in ["Track", "Volume", "", "", "Pan", "Offset"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for track in &mut self.tracks {
+                        ui.label(&track.label);
+                        ui.add(
+                            egui::Slider::new(&mut track.volume, 0.0..=2.0)
+                                .custom_formatter(|v, _| format!("{:.0}%", v * 100.0)),
+                        );
+                        ui.toggle_value(&mut track.mute, "M").on_hover_text("Mute");
+                        ui.toggle_value(&mut track.solo, "S").on_hover_text("Solo");
+                        ui.add(egui::Slider::new(&mut track.pan, -1.0..=1.0));
+                        ui.add(
+                            egui::DragValue::new(&mut track.offset)
+                                .speed(0.01)
+                                .range(-60.0..=60.0)
+                                .suffix(" s"),
+                        )
+                        .on_hover_text("Positive values delay the track, negative ones skip ahead");
+                        ui.end_row();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Reset").clicked() {
+                        for track in &mut self.tracks {
+                            track.volume = 1.0;
+                            track.mute = false;
+                            track.solo = false;
+                            track.pan = 0.0;
+                            track.offset = 0.0;
+                        }
+                    }
+                    if ui.button("Disable mixing").clicked() {
+                        disable = true;
+                    }
+                });
+                if let Some(graph) = &self.applied_graph {
+                    ui.collapsing("lavfi-complex", |ui| {
+                        ui.code(graph);
+                    });
+                }
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::YELLOW, format!("⚠ {error}"));
+                }
+            });
+        if disable {
+            core.mpv_handler.ipc(|b| b.switch_to_track(1));
+            self.applied_graph = None;
+            return;
+        }
+        // Added or removed tracks change the graph too, so they are applied here as well
+        let mixing = self.applied_graph.is_some() || (self.mix_added && self.tracks.len() >= 2);
+        if self.tracks != before
+            && mixing
+            && let Some(graph) = lavfi_graph(&self.tracks)
+        {
+            self.mix_added = false;
+            self.apply(core, graph);
+        }
+    }
+    /// Keep the model in sync with the audio tracks mpv has
+    fn sync_tracks(&mut self, audio_tracks: &[Track]) {
+        self.tracks
+            .retain(|mt| audio_tracks.iter().any(|t| t.id == mt.id));
+        for track in audio_tracks {
+            if !self.tracks.iter().any(|mt| mt.id == track.id) {
+                self.tracks.push(MixerTrack::new(track));
+            }
+        }
+    }
+    fn apply(&mut self, core: &mut Core, graph: String) {
+        match core
+            .mpv_handler
+            .ipc(|b| b.set_property_checked::<LavfiComplex>(graph.clone()))
+        {
+            Some(Ok(request)) => {
+                self.pending = Some(request);
+                self.applied_graph = Some(graph);
+            }
+            Some(Err(e)) => self.error = Some(e.to_string()),
+            None => {}
+        }
+    }
+}
+
+/// Generate a `lavfi-complex` graph mixing the tracks together.
+///
+/// Returns `None` if there is nothing to mix.
+fn lavfi_graph(tracks: &[MixerTrack]) -> Option<String> {
+    let first = tracks.first()?;
+    let any_solo = tracks.iter().any(|t| t.solo);
+    let audible: Vec<&MixerTrack> = tracks
+        .iter()
+        .filter(|t| !t.mute && (!any_solo || t.solo))
+        .collect();
+    if audible.is_empty() {
+        return Some(format!("[aid{}] volume=0 [ao]", first.id));
+    }
+    let mut graph = String::new();
+    for (i, track) in audible.iter().enumerate() {
+        let left = (1.0 - track.pan).min(1.0);
+        let right = (1.0 + track.pan).min(1.0);
+        write!(
+            graph,
+            "[aid{}] volume={:.3},aformat=channel_layouts=stereo,\
+             pan=stereo|c0={left:.3}*c0|c1={right:.3}*c1",
+            track.id, track.volume
+        )
+        .ok()?;
+        if track.offset > 0.0 {
+            let ms = (track.offset * 1000.0).round();
+            write!(graph, ",adelay=delays={ms}:all=1").ok()?;
+        } else if track.offset < 0.0 {
+            write!(
+                graph,
+                ",atrim=start={:.3},asetpts=PTS-STARTPTS",
+                -track.offset
+            )
+            .ok()?;
+        }
+        write!(graph, " [mix{i}]; ").ok()?;
+    }
+    for i in 0..audible.len() {
+        write!(graph, "[mix{i}] ").ok()?;
+    }
+    write!(graph, "amix=inputs={}:normalize=0 [ao]", audible.len()).ok()?;
+    Some(graph)
+}
+
+#[test]
+fn test_lavfi_graph() {
+    let track = |id, mute, solo, pan, offset| MixerTrack {
+        id,
+        label: String::new(),
+        volume: 1.0,
+        mute,
+        solo,
+        pan,
+        offset,
+    };
+    assert_eq!(lavfi_graph(&[]), None);
+    assert_eq!(
+        lavfi_graph(&[
+            track(1, false, false, 0.0, 0.0),
+            track(2, false, false, -1.0, 1.5)
+        ])
+        .as_deref(),
+        Some(
+            "[aid1] volume=1.000,aformat=channel_layouts=stereo,\
+             pan=stereo|c0=1.000*c0|c1=1.000*c1 [mix0]; \
+             [aid2] volume=1.000,aformat=channel_layouts=stereo,\
+             pan=stereo|c0=1.000*c0|c1=0.000*c1,adelay=delays=1500:all=1 [mix1]; \
+             [mix0] [mix1] amix=inputs=2:normalize=0 [ao]"
+        )
+    );
+    // Solo wins over everything else, muted tracks are left out
+    assert_eq!(
+        lavfi_graph(&[
+            track(1, false, false, 0.0, 0.0),
+            track(2, false, true, 0.0, -2.0),
+            track(3, true, true, 0.0, 0.0),
+        ])
+        .as_deref(),
+        Some(
+            "[aid2] volume=1.000,aformat=channel_layouts=stereo,\
+             pan=stereo|c0=1.000*c0|c1=1.000*c1,atrim=start=2.000,asetpts=PTS-STARTPTS [mix0]; \
+             [mix0] amix=inputs=1:normalize=0 [ao]"
+        )
+    );
+    assert_eq!(
+        lavfi_graph(&[track(4, true, false, 0.0, 0.0)]).as_deref(),
+        Some("[aid4] volume=0 [ao]")
+    );
+}
@@ -1,5 +1,9 @@
 use {
-    crate::{app::core::Core, ipc::property::LavfiComplex, logln},
+    crate::{
+        app::core::Core,
+        ipc::{Request, property::LavfiComplex},
+        logln,
+    },
     egui_sf2g::egui,
 };
 
@@ -7,15 +11,38 @@ use {
 pub struct MpvConsoleWindow {
     pub open: bool,
     cmd_buf: String,
+    /// Property get request we're waiting on, along with the property name
+    pending_get: Option<(String, Request<serde_json::Value>)>,
+    /// Properties being observed, with their values shown in the window
+    watched: Vec<String>,
 }
 
 const HELP: &str = "\
 Help:
     lavfi <str> - Set lavfi-complex filter
+    get <property> - Print the value of a property to the log
+    watch <property> - Keep showing the value of a property
+    unwatch <property> - Stop showing the value of a property
 ";
 
 impl MpvConsoleWindow {
     pub(crate) fn update(&mut self, core: &mut Core, ctx: &egui::Context) {
+        if let Some((name, request)) = &self.pending_get {
+            match core.mpv_handler.ipc(|ipc| ipc.poll(request)) {
+                Some(Some(Ok(value))) => {
+                    logln!("{name} = {value}");
+                    self.pending_get = None;
+                }
+                Some(Some(Err(e))) => {
+                    logln!("Failed to get {name}: {e}");
+                    self.pending_get = None;
+                }
+                // Not responded yet
+                Some(None) => {}
+                // Mpv went away
+                None => self.pending_get = None,
+            }
+        }
         egui::Window::new("🖳 Mpv console")
             .open(&mut self.open)
             .show(ctx, |ui| {
@@ -28,10 +55,42 @@ impl MpvConsoleWindow {
                                 core.mpv_handler
                                     .ipc(|ipc| ipc.set_property::<LavfiComplex>(args.into()));
                             }
+                            "get" => {
+                                match core.mpv_handler.ipc(|ipc| ipc.get_property_by_name(args)) {
+                                    Some(Ok(request)) => {
+                                        self.pending_get = Some((args.to_owned(), request));
+                                    }
+                                    Some(Err(e)) => logln!("Failed to get {args}: {e}"),
+                                    None => logln!("mpv is not running"),
+                                }
+                            }
+                            "watch" => {
+                                if !self.watched.iter().any(|name| name == args) {
+                                    core.mpv_handler.observe(args);
+                                    self.watched.push(args.to_owned());
+                                }
+                            }
+                            "unwatch" => {
+                                if let Some(idx) = self.watched.iter().position(|name| name == args)
+                                {
+                                    core.mpv_handler.unobserve(args);
+                                    self.watched.remove(idx);
+                                }
+                            }
                             _ => logln!("Unknown command: {cmd}"),
                         }
                     };
                 }
+                for name in &self.watched {
+                    let value = core
+                        .mpv_handler
+                        .ipc(|ipc| ipc.observed.get_raw(name).map(ToString::to_string))
+                        .flatten();
+                    ui.label(format!(
+                        "{name} = {}",
+                        value.as_deref().unwrap_or("<unavailable>")
+                    ));
+                }
                 ui.code(crate::app::LOG.lock().unwrap().as_str());
             });
     }
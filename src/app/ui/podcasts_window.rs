@@ -0,0 +1,209 @@
+use {
+    crate::{
+        app::{
+            ModalPopup,
+            core::Core,
+            playlist::Item,
+            podcasts::{EpisodeState, Podcasts},
+        },
+        time_fmt::FfmpegTimeFmt,
+    },
+    egui_sf2g::egui,
+    std::path::PathBuf,
+};
+
+#[derive(Default)]
+pub struct PodcastsWindow {
+    pub open: bool,
+    feed_url: String,
+    /// Text of the download directory field, filled in from the config when the window opens
+    download_dir: Option<String>,
+}
+
+enum Action {
+    Refresh(String),
+    Unsubscribe(String),
+    Play {
+        feed_url: String,
+        guid: String,
+    },
+    Download {
+        feed_url: String,
+        guid: String,
+    },
+    SetState {
+        feed_url: String,
+        guid: String,
+        state: EpisodeState,
+    },
+}
+
+impl PodcastsWindow {
+    /// Returns whether the episodes in the playlist changed
+    pub(crate) fn update(
+        &mut self,
+        core: &mut Core,
+        ctx: &egui::Context,
+        modal: &mut ModalPopup,
+    ) -> bool {
+        if !self.open {
+            self.download_dir = None;
+            return false;
+        }
+        let mut actions = Vec::new();
+        let mut open = self.open;
+        egui::Window::new("🎙 Podcasts")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.settings_ui(ui, core);
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.feed_url).hint_text("Feed URL"));
+                    let url = self.feed_url.trim();
+                    if ui
+                        .add_enabled(!url.is_empty(), egui::Button::new("Subscribe"))
+                        .clicked()
+                    {
+                        core.podcasts.subscribe(url.to_owned());
+                        self.feed_url.clear();
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    feeds_ui(ui, &core.podcasts, &mut actions);
+                });
+            });
+        self.open = open;
+        let mut changed = false;
+        for action in actions {
+            match action {
+                Action::Refresh(url) => core.podcasts.refresh(url),
+                Action::Unsubscribe(url) => {
+                    core.podcasts.unsubscribe(&url);
+                    core.set_episodes();
+                    changed = true;
+                }
+                Action::Play { feed_url, guid } => {
+                    let idx = core.playlist.iter().position(|item| {
+                        matches!(item, Item::Episode { feed_url: f, guid: g, .. }
+                            if *f == feed_url && *g == guid)
+                    });
+                    if let Some(idx) = idx {
+                        core.selected_song = idx;
+                        core.switch_to_selected_song(modal);
+                    }
+                }
+                Action::Download { feed_url, guid } => {
+                    core.podcasts.download(&core.cfg.podcasts, &feed_url, &guid);
+                }
+                Action::SetState {
+                    feed_url,
+                    guid,
+                    state,
+                } => {
+                    core.podcasts.set_state(&feed_url, &guid, state);
+                    core.podcasts.save();
+                }
+            }
+        }
+        changed
+    }
+    fn settings_ui(&mut self, ui: &mut egui::Ui, core: &mut Core) {
+        let cfg = &mut core.cfg.podcasts;
+        ui.horizontal(|ui| {
+            ui.label("Refresh every");
+            ui.add(
+                egui::DragValue::new(&mut cfg.refresh_interval)
+                    .range(0..=24 * 60)
+                    .suffix(" min"),
+            )
+            .on_hover_text("0 means only refresh manually");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Download directory");
+            let text = self.download_dir.get_or_insert_with(|| {
+                cfg.download_dir
+                    .as_ref()
+                    .map(|dir| dir.display().to_string())
+                    .unwrap_or_default()
+            });
+            let re = ui.add(egui::TextEdit::singleline(text).hint_text("<data directory>"));
+            if re.lost_focus() {
+                let dir = text.trim();
+                cfg.download_dir = (!dir.is_empty()).then(|| PathBuf::from(dir));
+            }
+        });
+    }
+}
+
+fn feeds_ui(ui: &mut egui::Ui, podcasts: &Podcasts, actions: &mut Vec<Action>) {
+    if podcasts.feeds.is_empty() {
+        ui.label("No subscriptions");
+    }
+    for feed in &podcasts.feeds {
+        egui::CollapsingHeader::new(&feed.title)
+            .id_salt(&feed.url)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    if podcasts.is_refreshing(&feed.url) {
+                        ui.spinner();
+                    } else if ui.button("⟳ Refresh").clicked() {
+                        actions.push(Action::Refresh(feed.url.clone()));
+                    }
+                    if ui.button("Unsubscribe").clicked() {
+                        actions.push(Action::Unsubscribe(feed.url.clone()));
+                    }
+                });
+                for episode in &feed.episodes {
+                    let ids = || (feed.url.clone(), episode.guid.clone());
+                    ui.horizontal(|ui| {
+                        let state_label = match episode.state {
+                            EpisodeState::New => "🆕".to_owned(),
+                            EpisodeState::InProgress(pos) => format!("⏸ {}", FfmpegTimeFmt(pos)),
+                            EpisodeState::Played => "✔".to_owned(),
+                        };
+                        ui.label(state_label);
+                        if ui.button("▶").on_hover_text("Play").clicked() {
+                            let (feed_url, guid) = ids();
+                            actions.push(Action::Play { feed_url, guid });
+                        }
+                        if episode.downloaded.is_some() {
+                            ui.label("💾").on_hover_text("Downloaded");
+                        } else if podcasts.is_downloading(&episode.guid) {
+                            ui.spinner();
+                        } else if ui.button("⬇").on_hover_text("Download").clicked() {
+                            let (feed_url, guid) = ids();
+                            actions.push(Action::Download { feed_url, guid });
+                        }
+                        let re = ui.label(&episode.title);
+                        re.context_menu(|ui| {
+                            let new_state = if episode.state == EpisodeState::Played {
+                                ("Mark as unplayed", EpisodeState::New)
+                            } else {
+                                ("Mark as played", EpisodeState::Played)
+                            };
+                            if ui.button(new_state.0).clicked() {
+                                let (feed_url, guid) = ids();
+                                actions.push(Action::SetState {
+                                    feed_url,
+                                    guid,
+                                    state: new_state.1,
+                                });
+                                ui.close();
+                            }
+                        });
+                        if let Some(date) = &episode.published {
+                            ui.weak(date);
+                        }
+                    });
+                    if !episode.notes.is_empty() {
+                        egui::CollapsingHeader::new("Show notes")
+                            .id_salt((&feed.url, &episode.guid))
+                            .show(ui, |ui| {
+                                ui.label(&episode.notes);
+                            });
+                    }
+                }
+            });
+    }
+}
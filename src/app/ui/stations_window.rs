@@ -0,0 +1,84 @@
+use {
+    crate::{
+        app::{ModalPopup, core::Core, playlist::Item},
+        config::Station,
+    },
+    egui_sf2g::egui,
+};
+
+#[derive(Default)]
+pub struct StationsWindow {
+    pub open: bool,
+    new_name: String,
+    new_url: String,
+}
+
+impl StationsWindow {
+    /// Returns whether the stations changed, in which case the playlist was updated
+    pub(crate) fn update(
+        &mut self,
+        core: &mut Core,
+        ctx: &egui::Context,
+        modal: &mut ModalPopup,
+    ) -> bool {
+        let mut changed = false;
+        let mut play = None;
+        let mut open = self.open;
+        egui::Window::new("📻 Stations")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let mut remove = None;
+                egui::Grid::new("stations_grid").show(ui, |ui| {
+                    for (i, station) in core.cfg.stations.iter_mut().enumerate() {
+                        changed |= ui.text_edit_singleline(&mut station.name).lost_focus();
+                        changed |= ui.text_edit_singleline(&mut station.url).lost_focus();
+                        if ui.button("▶").on_hover_text("Play").clicked() {
+                            play = Some(station.url.clone());
+                        }
+                        if ui.button("🗑").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                    ui.add(egui::TextEdit::singleline(&mut self.new_name).hint_text("Name"));
+                    ui.add(egui::TextEdit::singleline(&mut self.new_url).hint_text("URL"));
+                    let url = self.new_url.trim();
+                    if ui
+                        .add_enabled(!url.is_empty(), egui::Button::new("Add"))
+                        .clicked()
+                    {
+                        let name = match self.new_name.trim() {
+                            "" => url.to_owned(),
+                            name => name.to_owned(),
+                        };
+                        core.cfg.stations.push(Station {
+                            name,
+                            url: url.to_owned(),
+                        });
+                        self.new_name.clear();
+                        self.new_url.clear();
+                        changed = true;
+                    }
+                    ui.end_row();
+                });
+                if let Some(idx) = remove {
+                    core.cfg.stations.remove(idx);
+                    changed = true;
+                }
+            });
+        self.open = open;
+        if changed {
+            core.set_stations();
+        }
+        if let Some(play_url) = play
+            && let Some(idx) = core
+                .playlist
+                .iter()
+                .position(|item| matches!(item, Item::Url { url, .. } if *url == play_url))
+        {
+            core.selected_song = idx;
+            core.switch_to_selected_song(modal);
+        }
+        changed
+    }
+}
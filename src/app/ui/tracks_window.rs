@@ -0,0 +1,120 @@
+use {
+    crate::{
+        app::{ModalPopup, core::Core},
+        ipc::{
+            property::{Property as _, TrackList},
+            track_list::{Track, TrackKind},
+        },
+        util::result_ext::ResultModalExt as _,
+    },
+    egui_sf2g::egui,
+};
+
+#[derive(Default)]
+pub struct TracksWindow {
+    pub open: bool,
+    /// Whether we asked mpv to observe the track list
+    observing: bool,
+}
+
+impl TracksWindow {
+    pub(crate) fn update(&mut self, core: &mut Core, ctx: &egui::Context, modal: &mut ModalPopup) {
+        // Only keep the track list up to date while someone is looking at it
+        if self.open != self.observing {
+            if self.open {
+                core.mpv_handler.observe(TrackList::NAME);
+            } else {
+                core.mpv_handler.unobserve(TrackList::NAME);
+            }
+            self.observing = self.open;
+        }
+        if !self.open {
+            return;
+        }
+        let tracks = core
+            .mpv_handler
+            .ipc(|b| b.observed.get::<TrackList>())
+            .flatten()
+            .unwrap_or_default();
+        egui::Window::new("🎚 Tracks")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                if tracks.is_empty() {
+                    ui.label("No tracks");
+                    return;
+                }
+                for kind in [
+                    TrackKind::Audio,
+                    TrackKind::Video,
+                    TrackKind::Sub,
+                    TrackKind::Other,
+                ] {
+                    let tracks: Vec<&Track> = tracks.iter().filter(|t| t.kind == kind).collect();
+                    if tracks.is_empty() {
+                        continue;
+                    }
+                    ui.heading(kind.label());
+                    egui::Grid::new(kind.label()).striped(true).show(ui, |ui| {
+                        for header in ["", "#", "Language", "Title", "Codec", "Channels", ""] {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+                        for track in tracks {
+                            track_row_ui(ui, track, core, modal);
+                            ui.end_row();
+                        }
+                    });
+                }
+            });
+    }
+}
+
+fn track_row_ui(ui: &mut egui::Ui, track: &Track, core: &mut Core, modal: &mut ModalPopup) {
+    ui.label(if track.selected { "▶" } else { "" });
+    ui.label(track.id.to_string());
+    ui.label(track.lang.as_deref().unwrap_or("-"));
+    let title = track
+        .title
+        .as_deref()
+        .or(track.external_filename.as_deref())
+        .unwrap_or("-");
+    ui.label(title);
+    ui.label(track.codec.as_deref().unwrap_or("-"));
+    let channels = match (&track.channels, track.channel_count) {
+        (Some(layout), _) => layout.clone(),
+        (None, Some(count)) => count.to_string(),
+        (None, None) => "-".into(),
+    };
+    ui.label(channels);
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(!track.selected, egui::Button::new("Switch"))
+            .clicked()
+        {
+            core.mpv_handler
+                .ipc(|b| b.select_track(track.kind, track.id))
+                .err_popup("Failed to switch track", modal);
+        }
+        if track.kind != TrackKind::Audio {
+            return;
+        }
+        if ui
+            .add_enabled(track.id != 1, egui::Button::new("Mix"))
+            .on_hover_text("Mix with track 1")
+            .clicked()
+        {
+            core.mpv_handler
+                .ipc(|b| b.mix_t1_with_track(track.id))
+                .err_popup("Failed to mix track", modal);
+        }
+        if ui
+            .add_enabled(track.external, egui::Button::new("Remove"))
+            .on_disabled_hover_text("Only external tracks can be removed")
+            .clicked()
+        {
+            core.mpv_handler
+                .ipc(|b| b.remove_track(track.id))
+                .err_popup("Error removing track", modal);
+        }
+    });
+}
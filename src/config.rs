@@ -1,6 +1,7 @@
 //! Persistent configuration for the application
 
 use {
+    crate::effects::{EffectPreset, EffectsRack, PitchShift, TempoEngine},
     directories::ProjectDirs,
     enum_kinds::EnumKind,
     serde::{Deserialize, Deserializer, Serialize},
@@ -20,9 +21,14 @@ pub struct Config {
     pub custom_demuxers: Vec<CustomDemuxerEntry>,
     #[serde(default = "default_volume")]
     pub volume: u8,
+    /// Playback tempo (mpv `speed`). The pitch is preserved by [`Self::tempo_engine`].
     #[serde(default = "default_speed")]
     pub speed: f64,
     #[serde(default)]
+    pub tempo_engine: TempoEngine,
+    #[serde(default)]
+    pub pitch: PitchShift,
+    #[serde(default)]
     pub video: bool,
     #[serde(default)]
     pub theme: Option<ThemeColors>,
@@ -32,9 +38,179 @@ pub struct Config {
     /// Skip hidden files/folders
     #[serde(default)]
     pub skip_hidden: bool,
+    /// List the songs inside zip/7z/rar archives
+    #[serde(default)]
+    pub scan_archives: bool,
     /// Paths to fallback fonts to load on startup
     #[serde(default)]
     pub fallback_font_paths: Vec<String>,
+    /// Fade durations for playback transitions
+    #[serde(default)]
+    pub fade: FadeConfig,
+    /// Stop playback after this many files in a row failed to play
+    #[serde(default = "default_max_consecutive_failures")]
+    pub max_consecutive_failures: u32,
+    /// Relaunch the song at the last position if mpv or the demuxer crashes
+    #[serde(default)]
+    pub auto_recover: bool,
+    #[serde(default = "default_max_recovery_attempts")]
+    pub max_recovery_attempts: u32,
+    /// Path of the mpv IPC socket, with a number added for each mpv instance
+    /// (e.g. `mpv-1.sock` for `mpv.sock`). If not set, a unique path is generated.
+    #[serde(default)]
+    pub ipc_socket_path: Option<PathBuf>,
+    /// Audio effects currently in use
+    #[serde(default)]
+    pub effects: EffectsRack,
+    #[serde(default)]
+    pub effect_presets: Vec<EffectPreset>,
+    #[serde(default)]
+    pub replaygain: ReplayGainConfig,
+    #[serde(default)]
+    pub trainer: TrainerConfig,
+    /// Internet radio stations
+    #[serde(default)]
+    pub stations: Vec<Station>,
+    #[serde(default)]
+    pub podcasts: PodcastConfig,
+    #[serde(default)]
+    pub audiobook: AudiobookConfig,
+}
+
+/// Settings of the audiobook mode
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AudiobookConfig {
+    /// Folders that are treated as audiobooks (relative to the music folder)
+    pub folders: Vec<PathBuf>,
+    /// How far the back/forward buttons skip, in seconds
+    pub skip_seconds: f64,
+    /// Rewind a bit when resuming after a pause of at least this many minutes
+    pub rewind_after: u32,
+    /// How much to rewind when resuming, in seconds
+    pub rewind_by: f64,
+    /// Sleep timer started when playing a book, in minutes. 0 disables it.
+    pub sleep_timer: u32,
+}
+
+impl Default for AudiobookConfig {
+    fn default() -> Self {
+        Self {
+            folders: Vec::new(),
+            skip_seconds: 30.0,
+            rewind_after: 5,
+            rewind_by: 10.0,
+            sleep_timer: 30,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PodcastConfig {
+    /// Where episodes are downloaded to. A directory in the data dir if not set.
+    pub download_dir: Option<PathBuf>,
+    /// Minutes between refreshes of the feeds. 0 means only refresh manually.
+    pub refresh_interval: u32,
+}
+
+impl Default for PodcastConfig {
+    fn default() -> Self {
+        Self {
+            download_dir: None,
+            refresh_interval: 60,
+        }
+    }
+}
+
+/// A saved stream URL, like an HTTP or HLS internet radio station
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Station {
+    pub name: String,
+    pub url: String,
+}
+
+/// Settings of the practice trainer
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TrainerConfig {
+    pub start_speed: f64,
+    pub target_speed: f64,
+    /// How much the speed is increased by at a time
+    pub step: f64,
+    /// Repetitions to do before increasing the speed
+    pub reps_per_step: u32,
+    /// Pause between repetitions, in seconds
+    pub count_in: f64,
+}
+
+impl Default for TrainerConfig {
+    fn default() -> Self {
+        Self {
+            start_speed: 0.7,
+            target_speed: 1.0,
+            step: 0.05,
+            reps_per_step: 3,
+            count_in: 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReplayGainConfig {
+    pub mode: ReplayGainMode,
+    /// dB
+    pub preamp: f64,
+    /// Lower the gain if it would cause clipping
+    pub clip_prevention: bool,
+    /// Measure the loudness of files without ReplayGain tags in the background
+    pub analyze_untagged: bool,
+    /// Loudness the analyzed files are normalized to, in LUFS
+    pub target_loudness: f64,
+}
+
+impl Default for ReplayGainConfig {
+    fn default() -> Self {
+        Self {
+            mode: ReplayGainMode::Off,
+            preamp: 0.0,
+            clip_prevention: true,
+            analyze_untagged: false,
+            // The ReplayGain 2.0 reference level
+            target_loudness: -18.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+impl ReplayGainMode {
+    /// Value for mpv's `--replaygain` option
+    pub fn mpv_value(self) -> &'static str {
+        match self {
+            Self::Off => "no",
+            Self::Track => "track",
+            Self::Album => "album",
+        }
+    }
+}
+
+/// Fade durations in milliseconds. A duration of 0 disables the fade.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct FadeConfig {
+    pub pause_resume: u32,
+    pub stop: u32,
+    pub track_change: u32,
+    /// How much the end of a song overlaps with the start of the next one
+    pub crossfade: u32,
 }
 
 impl Default for Config {
@@ -44,11 +220,26 @@ impl Default for Config {
             custom_demuxers: Default::default(),
             volume: default_volume(),
             speed: default_speed(),
+            tempo_engine: TempoEngine::default(),
+            pitch: PitchShift::default(),
             video: false,
             theme: None,
             follow_symlinks: false,
             skip_hidden: false,
+            scan_archives: false,
             fallback_font_paths: Vec::new(),
+            fade: FadeConfig::default(),
+            max_consecutive_failures: default_max_consecutive_failures(),
+            auto_recover: false,
+            max_recovery_attempts: default_max_recovery_attempts(),
+            ipc_socket_path: None,
+            effects: EffectsRack::default(),
+            effect_presets: Vec::new(),
+            replaygain: ReplayGainConfig::default(),
+            trainer: TrainerConfig::default(),
+            stations: Vec::new(),
+            podcasts: PodcastConfig::default(),
+            audiobook: AudiobookConfig::default(),
         }
     }
 }
@@ -61,6 +252,14 @@ const fn default_speed() -> f64 {
     1.0
 }
 
+const fn default_max_consecutive_failures() -> u32 {
+    5
+}
+
+const fn default_max_recovery_attempts() -> u32 {
+    3
+}
+
 impl Config {
     pub fn load_if_exists() -> Option<anyhow::Result<Self>> {
         let path = Self::path();
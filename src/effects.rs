@@ -0,0 +1,204 @@
+//! Audio effects, applied through mpv's `af` property
+
+use {
+    serde::{Deserialize, Serialize},
+    std::path::PathBuf,
+};
+
+/// Center frequencies of the graphic equalizer bands, in Hz
+pub const EQ_BANDS: [u32; 10] = [31, 62, 125, 250, 500, 1000, 2000, 4000, 8000, 16000];
+
+/// The chain of effects applied to the audio, in processing order
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct EffectsRack {
+    pub equalizer: Equalizer,
+    pub tone: Tone,
+    pub stereo_width: StereoWidth,
+    /// Downmix to mono (on both channels)
+    pub mono: bool,
+    /// Cancel out the center channel, which usually removes the vocals
+    pub karaoke: bool,
+    pub compressor: Compressor,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Equalizer {
+    pub enabled: bool,
+    /// Gain of each band in [`EQ_BANDS`], in dB
+    pub gains: [f64; 10],
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct Tone {
+    pub enabled: bool,
+    /// dB
+    pub bass: f64,
+    /// dB
+    pub treble: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StereoWidth {
+    pub enabled: bool,
+    /// 1.0 is unchanged, 0.0 is mono, higher values widen the stereo image
+    pub width: f64,
+}
+
+impl Default for StereoWidth {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Compressor {
+    pub enabled: bool,
+    /// dB
+    pub threshold: f64,
+    pub ratio: f64,
+    /// ms
+    pub attack: f64,
+    /// ms
+    pub release: f64,
+    /// dB
+    pub makeup: f64,
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: -18.0,
+            ratio: 4.0,
+            attack: 20.0,
+            release: 250.0,
+            makeup: 0.0,
+        }
+    }
+}
+
+/// A named set of effects, optionally applied automatically for some songs
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct EffectPreset {
+    pub name: String,
+    pub rack: EffectsRack,
+    /// Apply to songs in these folders (relative to the music folder)
+    #[serde(default)]
+    pub folders: Vec<PathBuf>,
+    /// Apply to songs played with custom demuxers of these names
+    #[serde(default)]
+    pub demuxers: Vec<String>,
+}
+
+fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+impl EffectsRack {
+    /// The lavfi filter chain for the enabled effects, or `None` if nothing is enabled
+    pub fn lavfi_chain(&self) -> Option<String> {
+        let mut filters: Vec<String> = Vec::new();
+        if self.equalizer.enabled {
+            for (freq, gain) in EQ_BANDS.iter().zip(self.equalizer.gains) {
+                if gain != 0.0 {
+                    filters.push(format!("equalizer=f={freq}:t=o:w=1:g={gain:.1}"));
+                }
+            }
+        }
+        if self.tone.enabled {
+            filters.push(format!(
+                "bass=g={:.1},treble=g={:.1}",
+                self.tone.bass, self.tone.treble
+            ));
+        }
+        if self.stereo_width.enabled {
+            filters.push(format!(
+                "aformat=channel_layouts=stereo,extrastereo=m={:.2}",
+                self.stereo_width.width
+            ));
+        }
+        if self.karaoke {
+            filters.push("aformat=channel_layouts=stereo,pan=stereo|c0=c0-c1|c1=c1-c0".into());
+        }
+        if self.mono {
+            filters.push(
+                "aformat=channel_layouts=stereo,pan=stereo|c0=0.5*c0+0.5*c1|c1=0.5*c0+0.5*c1"
+                    .into(),
+            );
+        }
+        if self.compressor.enabled {
+            let c = &self.compressor;
+            filters.push(format!(
+                "acompressor=threshold={:.6}:ratio={:.1}:attack={:.1}:release={:.1}:makeup={:.3}",
+                db_to_linear(c.threshold).clamp(0.000_976_563, 1.0),
+                c.ratio.clamp(1.0, 20.0),
+                c.attack.clamp(0.01, 2000.0),
+                c.release.clamp(0.01, 9000.0),
+                db_to_linear(c.makeup).clamp(1.0, 64.0),
+            ));
+        }
+        (!filters.is_empty()).then(|| filters.join(","))
+    }
+    /// Value for mpv's `af` property
+    pub fn af(&self) -> String {
+        match self.lavfi_chain() {
+            Some(chain) => format!("@effects:lavfi=[{chain}]"),
+            None => String::new(),
+        }
+    }
+}
+
+/// Filter used to change the tempo without changing the pitch
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TempoEngine {
+    #[default]
+    ScaleTempo2,
+    /// Better quality, and also required for pitch shifting
+    Rubberband,
+}
+
+impl TempoEngine {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ScaleTempo2 => "scaletempo2",
+            Self::Rubberband => "rubberband",
+        }
+    }
+}
+
+/// Transposition of the audio, independent of the tempo
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct PitchShift {
+    pub semitones: i32,
+    pub cents: i32,
+}
+
+impl PitchShift {
+    pub fn is_zero(self) -> bool {
+        self.semitones == 0 && self.cents == 0
+    }
+    /// Frequency ratio of the shift
+    pub fn scale(self) -> f64 {
+        let cents = f64::from(self.semitones * 100 + self.cents);
+        2f64.powf(cents / 1200.0)
+    }
+}
+
+/// The mpv audio filter taking care of tempo changes (`speed`) and pitch shifting
+pub fn tempo_filter(engine: TempoEngine, pitch: PitchShift) -> String {
+    // scaletempo2 can't shift pitch, so rubberband is used for that regardless
+    if engine == TempoEngine::Rubberband || !pitch.is_zero() {
+        format!("@tempo:rubberband=pitch-scale={:.6}", pitch.scale())
+    } else {
+        "@tempo:scaletempo2".into()
+    }
+}
@@ -1,59 +1,260 @@
 //! Interprocess comunication with spawned mpv process
 
+pub mod chapter_list;
 mod command;
+mod line_buffer;
+pub mod prop_store;
 pub mod property;
+pub mod track_list;
 
 use {
     crate::{logln, util::result_ext::LogErrExt as _},
-    command::{AudioAdd, AudioRemove, Command, ObserveProperty, SetProperty},
+    command::{
+        AudioAdd, AudioRemove, Command, GetProperty, ObserveProperty, SetProperty,
+        UnobserveProperty,
+    },
+    directories::ProjectDirs,
     interprocess::local_socket::{
         GenericFilePath, Stream as LocalSocketStream, ToFsName, traits::Stream as _,
     },
-    property::{PropValue, Property},
+    line_buffer::LineBuffer,
+    prop_store::PropertyStore,
+    property::{FromJson, PropValue, Property},
     std::{
         collections::{HashMap, VecDeque},
+        fs::{DirBuilder, Permissions},
         io::{Read, Write},
         marker::PhantomData,
+        os::unix::fs::{DirBuilderExt as _, PermissionsExt as _},
+        path::{Path, PathBuf},
+        sync::atomic::{AtomicU64, Ordering},
     },
+    track_list::TrackKind,
 };
 
 pub enum IpcEvent {
-    EndFile,
+    /// An mpv command failed, and nobody is waiting for its result
+    CommandError {
+        /// The JSON of the command that failed
+        command: String,
+        error: String,
+        /// Whether the failure should only be logged
+        quiet: bool,
+    },
+    EndFile {
+        reason: EndFileReason,
+        /// Description of the error, if `reason` is [`EndFileReason::Error`]
+        file_error: Option<String>,
+    },
+}
+
+/// Why mpv stopped playing a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndFileReason {
+    Eof,
+    Stop,
+    Quit,
+    Error,
+    Redirect,
+    Unknown,
 }
 
+impl EndFileReason {
+    fn from_mpv(reason: &str) -> Self {
+        match reason {
+            "eof" => Self::Eof,
+            "stop" => Self::Stop,
+            "quit" => Self::Quit,
+            "error" => Self::Error,
+            "redirect" => Self::Redirect,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Request ids are unique across bridges, so a [`Request`] handle outliving its bridge
+/// can't pick up the result of an unrelated request sent to a newer mpv instance.
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
 pub struct Bridge {
     ipc_stream: LocalSocketStream,
-    pub observed: Properties,
+    pub observed: PropertyStore,
     pub event_queue: VecDeque<IpcEvent>,
+    observations: HashMap<String, Observation>,
+    next_observe_id: u64,
+    line_buffer: LineBuffer,
+    /// Requests sent to mpv that haven't been responded to yet
+    pending: HashMap<u64, PendingRequest>,
+    /// Responses to tracked requests, waiting to be picked up with [`Bridge::poll`]
+    results: HashMap<u64, Result<serde_json::Value, String>>,
+    /// Pause state that was requested (with the id of the request), but not confirmed yet.
+    ///
+    /// The state is `None` for a `get_property` request, whose response carries the state.
+    pending_pause: Option<(u64, Option<bool>)>,
+}
+
+struct PendingRequest {
+    /// The JSON of the command, for error reporting
+    command: String,
+    /// Whether someone is going to pick up the result.
+    ///
+    /// Errors of untracked requests are reported as [`IpcEvent::CommandError`].
+    tracked: bool,
+    /// See [`command::Command::QUIET`]
+    quiet: bool,
+}
+
+/// Handle to a request sent to mpv, which can be used to get its typed result
+#[must_use]
+pub struct Request<T> {
+    id: u64,
+    _value: PhantomData<fn() -> T>,
+}
+
+/// What mpv is currently doing
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlaybackState {
+    Stopped,
+    Playing,
+    Paused,
+    /// Waiting for the cache to fill (e.g. a slow network stream)
+    Buffering,
+    Seeking,
+}
+
+impl PlaybackState {
+    /// Whether playback is progressing, or about to
+    pub fn is_playing(self) -> bool {
+        matches!(self, Self::Playing | Self::Buffering | Self::Seeking)
+    }
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Stopped => "⏹ Stopped",
+            Self::Playing => "▶ Playing",
+            Self::Paused => "⏸ Paused",
+            Self::Buffering => "⏳ Buffering",
+            Self::Seeking => "⏩ Seeking",
+        }
+    }
+}
+
+/// An observation of a property, shared by everyone interested in it
+struct Observation {
+    /// The id mpv knows the observation by
+    id: u64,
+    /// How many times the property was asked to be observed
+    count: usize,
+}
+
+/// Location of the mpv IPC sockets of this instance
+pub struct SocketPath {
+    /// The sockets of the mpv instances are named after this
+    base: PathBuf,
+    /// Directory that was created just for this instance, to be removed on cleanup
+    own_dir: Option<PathBuf>,
+}
+
+impl SocketPath {
+    /// Use `configured` if provided, otherwise generate a socket path unique to this instance.
+    ///
+    /// The generated path is inside a directory that's only accessible by the current user,
+    /// preferably in `$XDG_RUNTIME_DIR`.
+    pub fn new(configured: Option<&Path>) -> Self {
+        if let Some(path) = configured {
+            return Self {
+                base: path.to_owned(),
+                own_dir: None,
+            };
+        }
+        let file_name = format!("mpv-{}.sock", std::process::id());
+        let runtime_dir = ProjectDirs::from("", "crumblingstatue", "mpvfrog")
+            .and_then(|dirs| dirs.runtime_dir().map(Path::to_owned));
+        if let Some(dir) = runtime_dir {
+            // The mode is only applied when the directory is created, but it might exist already
+            let result = DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&dir)
+                .and_then(|()| std::fs::set_permissions(&dir, Permissions::from_mode(0o700)));
+            match result {
+                Ok(()) => {
+                    return Self {
+                        base: dir.join(file_name),
+                        own_dir: None,
+                    };
+                }
+                Err(e) => logln!("Failed to set up runtime dir {}: {e}", dir.display()),
+            }
+        }
+        // No runtime dir, create a private directory in the temp dir.
+        // This fails if it already exists, so no one else can sneak in their own.
+        let suffix: u32 = rand::random();
+        let dir = std::env::temp_dir().join(format!("mpvfrog-{}-{suffix:08x}", std::process::id()));
+        if let Err(e) = DirBuilder::new().mode(0o700).create(&dir) {
+            logln!("Failed to create private socket dir {}: {e}", dir.display());
+        }
+        Self {
+            base: dir.join(file_name),
+            own_dir: Some(dir),
+        }
+    }
+    /// The socket of the mpv instance with the given generation.
+    ///
+    /// Each instance needs its own, because an instance that's still being crossfaded out
+    /// (or stopped) would remove the socket of the next one when it exits.
+    pub fn instance_path(&self, generation: u64) -> PathBuf {
+        let stem = self.base.file_stem().unwrap_or_default();
+        let mut name = stem.to_owned();
+        name.push(format!("-{generation}"));
+        if let Some(ext) = self.base.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+        self.base.with_file_name(name)
+    }
+    /// Remove the directory of the sockets if we created it.
+    ///
+    /// The sockets themselves are removed with [`remove_socket`] once their mpv instance
+    /// is gone.
+    pub fn cleanup(&self) {
+        if let Some(dir) = &self.own_dir {
+            std::fs::remove_dir(dir).log_err("Failed to remove ipc socket dir");
+        }
+    }
 }
 
-#[derive(Default)]
-pub struct Properties {
-    pub paused: bool,
-    pub volume: u8,
-    pub speed: f64,
-    pub duration: f64,
-    pub time_pos: f64,
-    pub ab_loop_a: Option<f64>,
-    pub ab_loop_b: Option<f64>,
-    pub track_count: u8,
-    pub lavfi_complex: String,
-    pub loop_file: bool,
-    pub playlist_pos: u64,
-    pub playlist_count: u64,
+/// Remove the socket of an mpv instance, if it's still there
+pub fn remove_socket(path: &Path) {
+    if let Err(e) = std::fs::remove_file(path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        logln!("Failed to remove ipc socket {}: {e}", path.display());
+    }
 }
 
 impl Bridge {
-    pub fn connect() -> anyhow::Result<Self> {
-        let ipc_stream = LocalSocketStream::connect(
-            "/tmp/mpvfrog.sock".to_fs_name::<GenericFilePath>().unwrap(),
-        )?;
+    pub fn connect(socket_path: &Path) -> anyhow::Result<Self> {
+        let ipc_stream = LocalSocketStream::connect(socket_path.to_fs_name::<GenericFilePath>()?)?;
         ipc_stream.set_nonblocking(true)?;
+        // mpv creates the socket according to the umask, make sure only we can use it
+        std::fs::set_permissions(socket_path, Permissions::from_mode(0o600))
+            .log_err("Failed to restrict ipc socket permissions");
         let mut this = Self {
             ipc_stream,
             observed: Default::default(),
             event_queue: Default::default(),
+            observations: HashMap::new(),
+            next_observe_id: 1,
+            line_buffer: LineBuffer::default(),
+            pending: HashMap::new(),
+            results: HashMap::new(),
+            pending_pause: None,
         };
+        this.observe_property::<property::Pause>()?;
+        this.observe_property::<property::IdleActive>()?;
+        this.observe_property::<property::EofReached>()?;
+        this.observe_property::<property::Seeking>()?;
+        this.observe_property::<property::PausedForCache>()?;
         this.observe_property::<property::Speed>()?;
         this.observe_property::<property::Volume>()?;
         this.observe_property::<property::TimePos>()?;
@@ -65,27 +266,152 @@ impl Bridge {
         this.observe_property::<property::LoopFile>()?;
         this.observe_property::<property::PlaylistPos>()?;
         this.observe_property::<property::PlaylistCount>()?;
+        this.observe_property::<property::MediaTitle>()?;
+        this.observe_property::<property::IcyTitle>()?;
+        this.observe_property::<property::Metadata>()?;
+        // mpv could have been started paused (e.g. with `--pause` in the extra args)
+        let pause_request = this.get_property::<property::Pause>()?;
+        this.pending_pause = Some((pause_request.id, None));
         Ok(this)
     }
     pub fn observe_property<T: Property>(&mut self) -> anyhow::Result<()> {
-        self.write_command(ObserveProperty::<T>(PhantomData))
+        self.observe_property_by_name(T::NAME)
+    }
+    /// Start observing a property, with its value becoming available in [`Self::observed`].
+    ///
+    /// Observations are counted, so every call should be paired with an unobserve call
+    /// once the property is no longer needed.
+    pub fn observe_property_by_name(&mut self, name: &str) -> anyhow::Result<()> {
+        if let Some(observation) = self.observations.get_mut(name) {
+            observation.count += 1;
+            return Ok(());
+        }
+        let id = self.next_observe_id;
+        self.next_observe_id += 1;
+        self.write_command(ObserveProperty { id, name })?;
+        self.observations
+            .insert(name.to_owned(), Observation { id, count: 1 });
+        Ok(())
+    }
+    pub fn unobserve_property_by_name(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(observation) = self.observations.get_mut(name) else {
+            return Ok(());
+        };
+        observation.count -= 1;
+        if observation.count == 0 {
+            let id = observation.id;
+            self.observations.remove(name);
+            self.observed.remove(name);
+            self.write_command(UnobserveProperty(id))?;
+        }
+        Ok(())
+    }
+    pub fn paused(&self) -> bool {
+        if let Some((_, Some(paused))) = self.pending_pause {
+            return paused;
+        }
+        self.observed.get::<property::Pause>().unwrap_or(false)
+    }
+    pub fn volume(&self) -> Option<u8> {
+        self.observed.get::<property::Volume>().map(|vol| vol as u8)
+    }
+    pub fn loop_file(&self) -> bool {
+        self.observed
+            .get_raw(property::LoopFile::NAME)
+            .and_then(serde_json::Value::as_str)
+            == Some("inf")
+    }
+    /// The playback state, as far as this mpv instance is concerned
+    pub fn playback_state(&self) -> PlaybackState {
+        let flag = |name| {
+            self.observed
+                .get_raw(name)
+                .and_then(serde_json::Value::as_bool)
+                .unwrap_or(false)
+        };
+        if flag(property::IdleActive::NAME) || flag(property::EofReached::NAME) {
+            PlaybackState::Stopped
+        } else if self.paused() {
+            PlaybackState::Paused
+        } else if flag(property::Seeking::NAME) {
+            PlaybackState::Seeking
+        } else if flag(property::PausedForCache::NAME) {
+            PlaybackState::Buffering
+        } else {
+            PlaybackState::Playing
+        }
     }
     pub fn toggle_pause(&mut self) -> anyhow::Result<()> {
-        // We assume here that the pause command will succeed.
-        //
-        // Yeah, I don't know what else to do here, because mpv doesn't seem
-        // to fire a pause event anymore when it gets paused.
-        self.observed.paused ^= true;
-        self.set_property::<property::Pause>(self.observed.paused)?;
+        self.set_pause(!self.paused())
+    }
+    pub fn set_pause(&mut self, paused: bool) -> anyhow::Result<()> {
+        // The observed pause property will catch up shortly, but until then
+        // the requested state is reported, and it is applied once mpv confirms it.
+        let request = self.set_property_checked::<property::Pause>(paused)?;
+        self.pending_pause = Some((request.id, Some(paused)));
         Ok(())
     }
+    /// Send a command without waiting for the result. Errors are reported as events.
     fn write_command<C: Command>(&mut self, command: C) -> anyhow::Result<()> {
-        let command_json = command.to_command_json();
+        self.send_command(command, false)?;
+        Ok(())
+    }
+    fn send_command<C: Command>(&mut self, command: C, tracked: bool) -> anyhow::Result<u64> {
+        let id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        let command_json = command.to_command_json(id);
         let mut serialized = serde_json::to_vec(&command_json).unwrap();
         // Commands need to be terminated with newline
         serialized.push(b'\n');
         self.ipc_stream.write_all(&serialized)?;
-        Ok(())
+        let command = serde_json::to_string(&command_json.command).unwrap();
+        self.pending.insert(
+            id,
+            PendingRequest {
+                command,
+                tracked,
+                quiet: C::QUIET,
+            },
+        );
+        Ok(id)
+    }
+    /// Send a command, and get a handle to its result
+    fn request<C: Command, T>(&mut self, command: C) -> anyhow::Result<Request<T>> {
+        Ok(Request {
+            id: self.send_command(command, true)?,
+            _value: PhantomData,
+        })
+    }
+    pub fn get_property<P: Property>(&mut self) -> anyhow::Result<Request<P::Value>>
+    where
+        P::Value: FromJson,
+    {
+        self.request(GetProperty::<P>(PhantomData))
+    }
+    /// Get a property by its name, for when it's not known at compile time
+    pub fn get_property_by_name(
+        &mut self,
+        name: &str,
+    ) -> anyhow::Result<Request<serde_json::Value>> {
+        self.request(command::GetPropertyByName(name))
+    }
+    /// Like [`Self::set_property`], but the result can be checked
+    pub fn set_property_checked<P: Property>(
+        &mut self,
+        value: P::Value,
+    ) -> anyhow::Result<Request<()>>
+    where
+        P::Value: PropValue,
+    {
+        self.request(SetProperty::<P>(value))
+    }
+    /// Get the result of a request, if mpv responded to it already
+    pub fn poll<T: FromJson>(&mut self, request: &Request<T>) -> Option<anyhow::Result<T>> {
+        let result = self.results.remove(&request.id)?;
+        Some(match result {
+            Ok(data) => T::from_json(&data)
+                .ok_or_else(|| anyhow::anyhow!("Unexpected response data: {data}")),
+            Err(e) => Err(anyhow::anyhow!(e)),
+        })
     }
     pub fn set_property<P: Property>(&mut self, value: P::Value) -> anyhow::Result<()>
     where
@@ -95,16 +421,18 @@ impl Bridge {
     }
     pub fn handle_responses(&mut self) -> anyhow::Result<()> {
         loop {
-            let mut buf = [0; 1000];
+            let mut buf = [0; 4096];
             match self.ipc_stream.read(&mut buf) {
                 Ok(amount) => {
                     if amount == 0 {
                         // Assume EOF and return
                         return Ok(());
                     }
-                    let string = std::str::from_utf8(&buf[..amount]).unwrap();
-                    for line in string.lines() {
-                        self.handle_response_line(line)
+                    self.line_buffer.push(&buf[..amount]);
+                    while let Some(line) = self.line_buffer.next_line() {
+                        if !line.is_empty() {
+                            self.handle_response_line(&line);
+                        }
                     }
                 }
                 Err(e) => match e.kind() {
@@ -117,24 +445,29 @@ impl Bridge {
     fn handle_response_line(&mut self, line: &str) {
         match serde_json::from_str::<HashMap<&str, serde_json::Value>>(line) {
             Ok(map) => {
-                if let Some(event) = map.get("event") {
+                if let Some(id) = map.get("request_id").and_then(serde_json::Value::as_u64) {
+                    self.handle_command_response(id, &map);
+                } else if let Some(event) = map.get("event") {
                     match event.as_str().unwrap() {
-                        "pause" => self.observed.paused = true,
-                        "unpause" => self.observed.paused = false,
                         "property-change" => {
                             let name = map.get("name").unwrap().as_str().unwrap();
-                            let Some(data) = map.get("data") else {
-                                logln!("data-less property change: {}", name);
-                                return;
-                            };
-                            if self.handle_property_change(name, data).is_none() {
-                                logln!(
-                                    "Failed to change property `{name}`: Type mismatch. Got {data}"
-                                );
+                            match map.get("data") {
+                                Some(data) => self.observed.set(name, data.clone()),
+                                // The property is unavailable (e.g. no file loaded)
+                                None => self.observed.remove(name),
                             }
                         }
                         "end-file" => {
-                            self.event_queue.push_back(IpcEvent::EndFile);
+                            let reason = map
+                                .get("reason")
+                                .and_then(serde_json::Value::as_str)
+                                .map_or(EndFileReason::Unknown, EndFileReason::from_mpv);
+                            let file_error = map
+                                .get("file_error")
+                                .and_then(serde_json::Value::as_str)
+                                .map(str::to_owned);
+                            self.event_queue
+                                .push_back(IpcEvent::EndFile { reason, file_error });
                         }
                         _ => logln!("Unhandled event: {}", event),
                     }
@@ -146,26 +479,44 @@ impl Bridge {
             }
         }
     }
-    fn handle_property_change(&mut self, name: &str, data: &serde_json::Value) -> Option<()> {
-        match name {
-            property::Speed::NAME => self.observed.speed = data.as_f64()?,
-            property::Volume::NAME => self.observed.volume = data.as_f64()? as u8,
-            property::Duration::NAME => self.observed.duration = data.as_f64()?,
-            property::TimePos::NAME => self.observed.time_pos = data.as_f64()?,
-            property::AbLoopA::NAME => self.observed.ab_loop_a = data.as_f64(),
-            property::AbLoopB::NAME => self.observed.ab_loop_b = data.as_f64(),
-            property::TrackListCount::NAME => self.observed.track_count = data.as_u64()? as u8,
-            property::LavfiComplex::NAME => self.observed.lavfi_complex = data.as_str()?.to_owned(),
-            property::LoopFile::NAME => self.observed.loop_file = data.as_str() == Some("inf"),
-            property::PlaylistCount::NAME => self.observed.playlist_count = data.as_u64()?,
-            property::PlaylistPos::NAME => self.observed.playlist_pos = data.as_u64()?,
-            name => logln!("Unhandled property: {} = {}", name, data),
+    fn handle_command_response(&mut self, id: u64, map: &HashMap<&str, serde_json::Value>) {
+        let Some(pending) = self.pending.remove(&id) else {
+            logln!("Response to unknown request {id}");
+            return;
+        };
+        let result = match map.get("error").and_then(serde_json::Value::as_str) {
+            Some("success") => Ok(map.get("data").cloned().unwrap_or_default()),
+            Some(error) => Err(error.to_owned()),
+            None => Err("Malformed response".into()),
+        };
+        if let Some((pause_id, paused)) = self.pending_pause
+            && pause_id == id
+        {
+            self.pending_pause = None;
+            match (result, paused) {
+                (Ok(_), Some(paused)) => self.observed.set(property::Pause::NAME, paused.into()),
+                (Ok(data), None) => self.observed.set(property::Pause::NAME, data),
+                (Err(e), _) => logln!("Failed to get or set pause: {e}"),
+            }
+            return;
+        }
+        if pending.tracked {
+            self.results.insert(id, result);
+        } else if let Err(error) = result {
+            self.event_queue.push_back(IpcEvent::CommandError {
+                command: pending.command,
+                error,
+                quiet: pending.quiet,
+            });
         }
-        Some(())
     }
     pub fn set_volume(&mut self, vol: u8) -> anyhow::Result<()> {
         self.set_property::<property::Volume>(vol as f64)
     }
+    /// Set the volume without rounding, used for smooth fades
+    pub fn set_volume_f64(&mut self, vol: f64) -> anyhow::Result<()> {
+        self.set_property::<property::Volume>(vol)
+    }
     pub fn set_speed(&mut self, speed: f64) -> anyhow::Result<()> {
         self.set_property::<property::Speed>(speed)
     }
@@ -173,15 +524,23 @@ impl Bridge {
         self.set_property::<property::TimePos>(pos)
     }
     pub fn set_video(&mut self, show: bool) -> anyhow::Result<()> {
-        self.set_property::<property::Video>(show.then_some("1"))
+        self.set_property::<property::Video>(show.then_some(1))
     }
     pub fn set_ab_loop(&mut self, a: Option<f64>, b: Option<f64>) -> anyhow::Result<()> {
         self.set_property::<property::AbLoopA>(a)?;
         self.set_property::<property::AbLoopB>(b)
     }
+    /// Jump to the start of a chapter
+    pub fn set_chapter(&mut self, idx: usize) -> anyhow::Result<()> {
+        self.set_property::<property::CurrentChapter>(idx.try_into()?)
+    }
+    /// Replace the audio filter chain
+    pub fn set_audio_filters(&mut self, af: String) -> anyhow::Result<()> {
+        self.set_property::<property::Af>(af)
+    }
+    /// Add an audio track. The mixer takes care of mixing it in.
     pub fn add_audio(&mut self, path: &str) -> anyhow::Result<()> {
-        self.write_command(AudioAdd(path))?;
-        self.set_property::<property::LavfiComplex>("[aid1] [aid2] amix [ao]".into())
+        self.write_command(AudioAdd(path))
     }
 
     pub fn mix_t1_with_track(&mut self, track: u64) -> anyhow::Result<()> {
@@ -195,6 +554,16 @@ impl Bridge {
         Ok(())
     }
 
+    /// Make a track the active one of its kind
+    pub(crate) fn select_track(&mut self, kind: TrackKind, id: u64) -> anyhow::Result<()> {
+        match kind {
+            TrackKind::Audio => self.switch_to_track(id),
+            TrackKind::Video => self.set_property::<property::Video>(Some(id)),
+            TrackKind::Sub => self.set_property::<property::Sid>(id),
+            TrackKind::Other => anyhow::bail!("Can't select a track of unknown type"),
+        }
+    }
+
     pub(crate) fn remove_track(&mut self, track_num: u64) -> anyhow::Result<()> {
         self.write_command(AudioRemove(track_num))?;
         Ok(())
@@ -207,6 +576,9 @@ impl Bridge {
     pub fn playlist_prev(&mut self) {
         let _ = self.write_command(command::PlaylistPrev);
     }
+    pub fn quit(&mut self) -> anyhow::Result<()> {
+        self.write_command(command::Quit)
+    }
     pub fn playlist_next(&mut self) {
         let _ = self.write_command(command::PlaylistNext);
     }
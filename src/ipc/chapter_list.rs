@@ -0,0 +1,26 @@
+//! Entries of the mpv `chapter-list` property
+
+use {super::property::FromJson, serde::Deserialize};
+
+#[derive(Deserialize, Clone)]
+pub struct Chapter {
+    pub title: Option<String>,
+    /// Start, in seconds
+    pub time: f64,
+}
+
+impl Chapter {
+    /// The title, or a generic name based on the index if there is none
+    pub fn display_title(&self, idx: usize) -> String {
+        match &self.title {
+            Some(title) if !title.is_empty() => title.clone(),
+            _ => format!("Chapter {}", idx + 1),
+        }
+    }
+}
+
+impl FromJson for Vec<Chapter> {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        Self::deserialize(value).ok()
+    }
+}
@@ -6,20 +6,61 @@ use {
 
 pub(super) trait Command {
     type R: Serialize;
+    /// Whether failures are only worth logging, not reporting to the user.
+    ///
+    /// Property commands are often sent continuously (e.g. while dragging a slider),
+    /// so reporting each failure would result in a barrage of popups.
+    const QUIET: bool = false;
     fn json_values(&self) -> Self::R;
-    fn to_command_json(&self) -> CommandJson<Self::R> {
+    fn to_command_json(&self, request_id: u64) -> CommandJson<Self::R> {
         CommandJson {
             command: self.json_values(),
+            request_id,
         }
     }
 }
 
-pub(super) struct ObserveProperty<T>(pub(super) PhantomData<T>);
+pub(super) struct ObserveProperty<'a> {
+    /// Used to refer to this observation when unobserving
+    pub(super) id: u64,
+    pub(super) name: &'a str,
+}
 
-impl<T: Property> Command for ObserveProperty<T> {
+impl Command for ObserveProperty<'_> {
     type R = [serde_json::Value; 3];
+    const QUIET: bool = true;
+    fn json_values(&self) -> Self::R {
+        ["observe_property".into(), self.id.into(), self.name.into()]
+    }
+}
+
+pub(super) struct UnobserveProperty(pub(super) u64);
+
+impl Command for UnobserveProperty {
+    type R = [serde_json::Value; 2];
+    const QUIET: bool = true;
     fn json_values(&self) -> Self::R {
-        ["observe_property".into(), 1.into(), T::NAME.into()]
+        ["unobserve_property".into(), self.0.into()]
+    }
+}
+
+pub(super) struct GetProperty<T>(pub(super) PhantomData<T>);
+
+impl<T: Property> Command for GetProperty<T> {
+    type R = [&'static str; 2];
+    const QUIET: bool = true;
+    fn json_values(&self) -> Self::R {
+        ["get_property", T::NAME]
+    }
+}
+
+pub(super) struct GetPropertyByName<'a>(pub(super) &'a str);
+
+impl Command for GetPropertyByName<'_> {
+    type R = [serde_json::Value; 2];
+    const QUIET: bool = true;
+    fn json_values(&self) -> Self::R {
+        ["get_property".into(), self.0.into()]
     }
 }
 
@@ -45,7 +86,9 @@ impl Command for AudioRemove {
 
 #[derive(Serialize)]
 pub(super) struct CommandJson<T: Serialize> {
-    command: T,
+    pub(super) command: T,
+    /// mpv includes this in the response, so we can tell which command it belongs to
+    request_id: u64,
 }
 
 pub(super) struct SetProperty<P: Property>(pub(super) P::Value);
@@ -55,6 +98,7 @@ where
     P::Value: PropValue,
 {
     type R = [serde_json::Value; 3];
+    const QUIET: bool = true;
     fn json_values(&self) -> Self::R {
         ["set_property".into(), P::NAME.into(), self.0.to_json()]
     }
@@ -78,3 +122,13 @@ impl Command for PlaylistNext {
         ["playlist-next"]
     }
 }
+
+pub(super) struct Quit;
+
+impl Command for Quit {
+    type R = [&'static str; 1];
+
+    fn json_values(&self) -> Self::R {
+        ["quit"]
+    }
+}
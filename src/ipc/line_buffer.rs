@@ -0,0 +1,91 @@
+//! Newline framing for the mpv IPC byte stream
+
+/// Splits a byte stream into newline terminated lines.
+///
+/// A read can end in the middle of a line (or even a multi-byte character), so incomplete
+/// data is kept around until the rest of the line arrives.
+#[derive(Default)]
+pub struct LineBuffer {
+    buf: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Append newly read bytes
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+    /// Take the next complete line out of the buffer, without the line terminator.
+    ///
+    /// Invalid UTF-8 is replaced with `U+FFFD`.
+    pub fn next_line(&mut self) -> Option<String> {
+        let end = self.buf.iter().position(|&b| b == b'\n')?;
+        let line = String::from_utf8_lossy(&self.buf[..end])
+            .trim_end_matches('\r')
+            .to_owned();
+        self.buf.drain(..=end);
+        Some(line)
+    }
+}
+
+#[test]
+fn test_line_buffer_chunkings() {
+    let mut input = Vec::new();
+    input.extend_from_slice(b"{\"event\":\"property-change\",\"name\":\"volume\",\"data\":50}\n");
+    input.extend_from_slice("{\"data\":\"月光 — ソナタ 🎹\"}\r\n".as_bytes());
+    input.extend_from_slice(b"{\"data\":\"bad \xff\xfe utf8\"}\n");
+    input.extend_from_slice(b"\n");
+    input.extend_from_slice("{\"data\":\"Ünïcödé\"}\n".as_bytes());
+    let expected = [
+        "{\"event\":\"property-change\",\"name\":\"volume\",\"data\":50}",
+        "{\"data\":\"月光 — ソナタ 🎹\"}",
+        "{\"data\":\"bad \u{FFFD}\u{FFFD} utf8\"}",
+        "",
+        "{\"data\":\"Ünïcödé\"}",
+    ];
+    let collect = |chunks: &mut dyn Iterator<Item = &[u8]>| {
+        let mut lb = LineBuffer::default();
+        let mut lines = Vec::new();
+        for chunk in chunks {
+            lb.push(chunk);
+            while let Some(line) = lb.next_line() {
+                lines.push(line);
+            }
+        }
+        assert!(lb.buf.is_empty());
+        lines
+    };
+    // Fixed chunk sizes
+    for size in 1..=input.len() {
+        assert_eq!(
+            collect(&mut input.chunks(size)),
+            expected,
+            "chunk size {size}"
+        );
+    }
+    // Every possible split into two, and three chunks
+    for i in 0..=input.len() {
+        let (a, b) = input.split_at(i);
+        assert_eq!(collect(&mut [a, b].into_iter()), expected, "split at {i}");
+        for j in i..=input.len() {
+            let (b, c) = input[i..].split_at(j - i);
+            assert_eq!(
+                collect(&mut [a, b, c].into_iter()),
+                expected,
+                "split at {i}, {j}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_line_buffer_partial_line_kept() {
+    let mut lb = LineBuffer::default();
+    lb.push("{\"data\":\"é".as_bytes().split_last().unwrap().1);
+    assert_eq!(lb.next_line(), None);
+    lb.push(&[0xA9]);
+    lb.push(b"\"}\n{\"incomplete\"");
+    assert_eq!(lb.next_line().as_deref(), Some("{\"data\":\"é\"}"));
+    assert_eq!(lb.next_line(), None);
+    lb.push(b":1}\n");
+    assert_eq!(lb.next_line().as_deref(), Some("{\"incomplete\":1}"));
+}
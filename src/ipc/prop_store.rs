@@ -0,0 +1,38 @@
+//! Storage for the values of observed mpv properties
+
+use {
+    super::property::{FromJson, Property},
+    std::collections::HashMap,
+};
+
+/// Latest known values of observed properties, keyed by property name
+#[derive(Default)]
+pub struct PropertyStore {
+    values: HashMap<String, serde_json::Value>,
+}
+
+impl PropertyStore {
+    /// Get the value of a property, if it's known and has the expected type
+    pub fn get<P: Property>(&self) -> Option<P::Value>
+    where
+        P::Value: FromJson,
+    {
+        self.values.get(P::NAME).and_then(P::Value::from_json)
+    }
+    /// Get the raw JSON value of a property by name
+    pub fn get_raw(&self, name: &str) -> Option<&serde_json::Value> {
+        self.values.get(name)
+    }
+    pub(super) fn set(&mut self, name: &str, value: serde_json::Value) {
+        match self.values.get_mut(name) {
+            Some(old) => *old = value,
+            None => {
+                self.values.insert(name.to_owned(), value);
+            }
+        }
+    }
+    /// Forget the value of a property (e.g. it became unavailable)
+    pub(super) fn remove(&mut self, name: &str) {
+        self.values.remove(name);
+    }
+}
@@ -19,6 +19,12 @@ impl PropValue for u64 {
     }
 }
 
+impl PropValue for i64 {
+    fn to_json(&self) -> serde_json::Value {
+        (*self).into()
+    }
+}
+
 impl PropValue for bool {
     fn to_json(&self) -> serde_json::Value {
         (*self).into()
@@ -34,6 +40,15 @@ impl PropValue for Option<f64> {
     }
 }
 
+impl PropValue for Option<u64> {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            &Some(num) => num.into(),
+            None => "no".into(),
+        }
+    }
+}
+
 impl PropValue for Option<&'static str> {
     fn to_json(&self) -> serde_json::Value {
         match self {
@@ -49,6 +64,60 @@ impl PropValue for String {
     }
 }
 
+/// Conversion from the JSON mpv responds with
+pub trait FromJson: Sized {
+    fn from_json(value: &serde_json::Value) -> Option<Self>;
+}
+
+impl FromJson for () {
+    fn from_json(_value: &serde_json::Value) -> Option<Self> {
+        Some(())
+    }
+}
+
+impl FromJson for serde_json::Value {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromJson for f64 {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_f64()
+    }
+}
+
+impl FromJson for u64 {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_u64()
+    }
+}
+
+impl FromJson for i64 {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_i64()
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl FromJson for String {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        value.as_str().map(str::to_owned)
+    }
+}
+
+impl FromJson for Option<f64> {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        // mpv uses "no" for unset values
+        Some(value.as_f64())
+    }
+}
+
 macro_rules! decl_property {
     ($tyname:ident, $attrname:literal, $valty:ty) => {
         pub enum $tyname {}
@@ -72,15 +141,31 @@ decl_properties! {
     Volume, "volume", f64;
     Speed, "speed", f64;
     Pause, "pause", bool;
+    IdleActive, "idle-active", bool;
+    EofReached, "eof-reached", bool;
+    Seeking, "seeking", bool;
+    PausedForCache, "paused-for-cache", bool;
     TimePos, "time-pos", f64;
     Duration, "duration", f64;
-    Video, "vid", Option<&'static str>;
+    // The video track id, or no video
+    Video, "vid", Option<u64>;
     AbLoopA, "ab-loop-a", Option<f64>;
     AbLoopB, "ab-loop-b", Option<f64>;
     LavfiComplex, "lavfi-complex", String;
+    Af, "af", String;
     Aid, "aid", u64;
     LoopFile, "loop-file", Option<&'static str>;
     TrackListCount, "track-list/count", u64;
+    TrackList, "track-list", Vec<super::track_list::Track>;
+    Sid, "sid", u64;
     PlaylistPos, "playlist-pos", u64;
     PlaylistCount, "playlist-count", u64;
+    MediaTitle, "media-title", String;
+    // Title of the current song of an internet radio stream (ICY metadata)
+    IcyTitle, "metadata/by-key/icy-title", String;
+    ChapterList, "chapter-list", Vec<super::chapter_list::Chapter>;
+    // Index of the current chapter, -1 before the first one
+    CurrentChapter, "chapter", i64;
+    // Tags of the file, as an object
+    Metadata, "metadata", serde_json::Value;
 }
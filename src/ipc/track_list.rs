@@ -0,0 +1,53 @@
+//! Entries of the mpv `track-list` property
+
+use {super::property::FromJson, serde::Deserialize};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackKind {
+    Audio,
+    Video,
+    Sub,
+    /// Any track type we don't know about
+    #[serde(other)]
+    Other,
+}
+
+impl TrackKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Audio => "Audio",
+            Self::Video => "Video",
+            Self::Sub => "Subtitles",
+            Self::Other => "Other",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Track {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: TrackKind,
+    pub title: Option<String>,
+    pub lang: Option<String>,
+    pub codec: Option<String>,
+    /// Channel layout, like "stereo" or "5.1"
+    #[serde(rename = "demux-channels")]
+    pub channels: Option<String>,
+    #[serde(rename = "demux-channel-count")]
+    pub channel_count: Option<u64>,
+    #[serde(default)]
+    pub selected: bool,
+    /// Loaded from a separate file (e.g. with `audio-add`)
+    #[serde(default)]
+    pub external: bool,
+    #[serde(rename = "external-filename")]
+    pub external_filename: Option<String>,
+}
+
+impl FromJson for Vec<Track> {
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        Self::deserialize(value).ok()
+    }
+}
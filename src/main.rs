@@ -14,6 +14,7 @@ use {clap::Parser, existing_instance::Msg, std::path::PathBuf};
 
 mod app;
 mod config;
+mod effects;
 mod ipc;
 mod mpv_handler;
 mod rect_math;
@@ -22,8 +23,11 @@ mod time_fmt;
 mod util {
     pub mod bool_ext;
     pub mod egui_ext;
+    pub mod hash;
+    pub mod json_store;
     pub mod result_ext;
-    pub mod str_ext;
+    pub mod url;
+    pub mod worker;
 }
 
 const APP_LABEL: &str = "🐸 mpvfrog";
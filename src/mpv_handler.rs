@@ -4,26 +4,129 @@ use {
     crate::{
         app::ModalPopup,
         config::ArgType,
-        ipc::{self, IpcEvent},
+        ipc::{
+            self, IpcEvent, PlaybackState,
+            chapter_list::Chapter,
+            property::{self, Property as _},
+        },
         logln,
+        util::result_ext::LogErrExt as _,
     },
     ansi_term_buf::Term,
     anyhow::Context,
+    nix::{
+        sys::signal::{Signal, kill},
+        unistd::Pid,
+    },
     nonblock::NonBlockingReader,
     pty_process::blocking::{Command as PtyCommand, Pty},
     std::{
+        collections::{HashMap, VecDeque},
         ffi::{OsStr, OsString},
         io::Read as _,
         io::Write as _,
-        process::{Child, Stdio},
+        path::{Path, PathBuf},
+        process::{Child, ExitStatus, Stdio},
+        sync::mpsc::{Receiver, Sender, channel},
+        thread::JoinHandle,
+        time::{Duration, Instant},
     },
 };
 
 struct MpvHandlerInner {
     child: Child,
     mpv_pty: Pty,
+    demuxer_child: Option<Child>,
     demuxer_pty: Pty,
     ipc_bridge: ipc::Bridge,
+    /// Which mpv instance this is, see [`MpvHandler::generation`]
+    generation: u64,
+    /// The IPC socket of this instance, removed once mpv is gone
+    socket_path: PathBuf,
+}
+
+impl MpvHandlerInner {
+    /// Ask mpv to quit, then stop and reap mpv and the demuxer in a background thread,
+    /// escalating to signals if they don't cooperate, so the ui doesn't have to wait for them.
+    ///
+    /// The exit status of the demuxer (if there was one) is sent to `demuxer_exits`.
+    fn quit(mut self, demuxer_exits: Sender<(u64, ExitStatus)>) -> JoinHandle<()> {
+        let exited = matches!(self.child.try_wait(), Ok(Some(_)));
+        // Ask nicely first
+        if !exited && let Err(e) = self.ipc_bridge.quit() {
+            logln!("Failed to send quit command to mpv: {e}");
+            self.mpv_pty
+                .write_all(b"q")
+                .log_err("Failed to write quit key to mpv pty");
+        }
+        std::thread::spawn(move || {
+            // Keep the ptys and the socket open until mpv is gone
+            let mut this = self;
+            if let Err(e) = terminate(&mut this.child, "mpv") {
+                logln!("Failed to stop mpv: {e}");
+            }
+            ipc::remove_socket(&this.socket_path);
+            // With mpv gone, the demuxer should exit on its own due to the broken pipe
+            if let Some(mut demux_child) = this.demuxer_child.take() {
+                match terminate(&mut demux_child, "demuxer") {
+                    Ok(status) => {
+                        // The receiver is only gone when the app is exiting
+                        let _ = demuxer_exits.send((this.generation, status));
+                    }
+                    Err(e) => logln!("Failed to stop demuxer: {e}"),
+                }
+            }
+        })
+    }
+}
+
+/// How long a child process gets to exit on its own before being sent SIGTERM
+const QUIT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long a child process gets to exit after SIGTERM before being killed
+const TERM_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Wait for `child` to exit, for at most `timeout`
+fn wait_timeout(child: &mut Child, timeout: Duration) -> std::io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Wait for `child` to exit, escalating to SIGTERM, then SIGKILL if it doesn't, and reap it.
+///
+/// The graceful quit request (if any) should already have been sent.
+fn terminate(child: &mut Child, name: &str) -> std::io::Result<ExitStatus> {
+    if let Some(status) = wait_timeout(child, QUIT_TIMEOUT)? {
+        return Ok(status);
+    }
+    logln!("{name} didn't quit in time, sending SIGTERM");
+    if let Err(e) = kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM) {
+        logln!("Failed to send SIGTERM to {name}: {e}");
+    }
+    if let Some(status) = wait_timeout(child, TERM_TIMEOUT)? {
+        return Ok(status);
+    }
+    logln!("{name} didn't terminate in time, killing it");
+    child.kill()?;
+    child.wait()
+}
+
+/// Kill and reap a child process without asking nicely, used when startup fails halfway
+fn kill_and_reap(child: &mut Child, name: &str) {
+    if let Err(e) = child.kill().and_then(|()| child.wait()) {
+        logln!("Failed to kill {name}: {e}");
+    }
+}
+
+fn demuxer_exit_msg(status: ExitStatus) -> String {
+    format!("\r\n[demuxer {status}]\r\n")
 }
 
 pub struct MpvHandler {
@@ -32,9 +135,30 @@ pub struct MpvHandler {
     /// Name of the demuxer command. This should be synchronized with the term, so the tab
     /// shows the same name as the command that produced the term output.
     pub demux_cmd_name: String,
+    /// Exit status of the last demuxer, once it exited
+    pub demux_exit_status: Option<ExitStatus>,
     inner: Option<MpvHandlerInner>,
+    /// Previous mpv instance that keeps playing while it's being crossfaded out
+    outgoing: Option<MpvHandlerInner>,
     read_demuxer: bool,
     pub active_pty_input: ActivePtyInput,
+    /// Events received from mpv. Kept here so they outlive the mpv process that sent them.
+    events: VecDeque<IpcEvent>,
+    /// Set when mpv terminates abnormally, describing what happened
+    abnormal_exit: Option<String>,
+    last_time_pos: f64,
+    ipc_socket: ipc::SocketPath,
+    /// Properties observed on demand, with the number of interested parties.
+    ///
+    /// These are re-observed whenever a new mpv instance is started.
+    extra_observed: HashMap<String, usize>,
+    /// Counts the started mpv instances, to tell which one a late exit status belongs to
+    generation: u64,
+    /// Threads stopping and reaping mpv instances that were quit
+    reapers: Vec<JoinHandle<()>>,
+    demuxer_exit_send: Sender<(u64, ExitStatus)>,
+    /// Exit statuses of the demuxers of quit mpv instances, with their generation
+    demuxer_exits: Receiver<(u64, ExitStatus)>,
 }
 
 pub enum ActivePtyInput {
@@ -56,13 +180,22 @@ impl MpvHandler {
     ) -> anyhow::Result<()> {
         self.read_demuxer = true;
         self.stop_music();
+        self.events.clear();
+        self.abnormal_exit = None;
+        self.last_time_pos = 0.0;
         self.mpv_term.reset();
         self.demux_term.reset();
         self.demux_cmd_name.clear();
+        self.demux_exit_status = None;
+        self.generation += 1;
         let (mut pty, pts) = pty_process::blocking::open()?;
         let mut mpv_command = PtyCommand::new(mpv_cmd);
         let (demuxer_pty, demux_pts) = pty_process::blocking::open()?;
-        mpv_command = mpv_command.args(mpv_args);
+        let socket_path = self.ipc_socket.instance_path(self.generation);
+        let mut ipc_arg = OsString::from("--input-ipc-server=");
+        ipc_arg.push(&socket_path);
+        mpv_command = mpv_command.args(mpv_args).args([ipc_arg]);
+        let mut demuxer_child = None;
         if let Some(demuxer) = custom_demuxer {
             logln!("Demuxer: {}, args: {:?}", demuxer.cmd, demuxer.args);
             self.demux_cmd_name = demuxer.cmd.clone();
@@ -72,53 +205,105 @@ impl MpvHandler {
                 .spawn(demux_pts)
                 .context("Failed to spawn demuxer")?;
             mpv_command = mpv_command.stdin(demux_child.stdout.take().unwrap());
+            demuxer_child = Some(demux_child);
         }
-        let mut child = mpv_command.spawn(pts)?;
-        let attempts = 5;
-        let ipc_bridge = 'connect: {
-            for i in 0..attempts {
-                std::thread::sleep(std::time::Duration::from_millis(100));
-                match ipc::Bridge::connect() {
-                    Ok(bridge) => break 'connect bridge,
-                    Err(e) => {
-                        if let Some(status) = child.try_wait()? {
-                            let mut stderr = Vec::new();
-                            let result = pty.read_to_end(&mut stderr);
-                            if let Err(e) = result {
-                                logln!("Failed to read mpv pty: {e}");
-                            }
-                            let mut term = Term::new(80);
-                            term.feed(&stderr);
-                            let stderr = term.contents_to_string();
-                            anyhow::bail!("mpv exited with {status}.\nStderr:\n{stderr}");
-                        }
-                        logln!("mpv connection attempt #{i}: {e}");
-                    }
+        let mut child = match mpv_command.spawn(pts) {
+            Ok(child) => child,
+            Err(e) => {
+                if let Some(demux_child) = &mut demuxer_child {
+                    kill_and_reap(demux_child, "demuxer");
+                }
+                return Err(e.into());
+            }
+        };
+        let mut ipc_bridge = match connect_ipc(&mut child, &mut pty, &socket_path) {
+            Ok(bridge) => bridge,
+            Err(e) => {
+                // Don't leave anything running behind
+                kill_and_reap(&mut child, "mpv");
+                if let Some(demux_child) = &mut demuxer_child {
+                    kill_and_reap(demux_child, "demuxer");
                 }
+                ipc::remove_socket(&socket_path);
+                return Err(e);
             }
-            anyhow::bail!("Failed connect to mpv");
         };
+        for name in self.extra_observed.keys() {
+            if let Err(e) = ipc_bridge.observe_property_by_name(name) {
+                logln!("Failed to observe property `{name}`: {e}");
+            }
+        }
         self.inner = Some(MpvHandlerInner {
             child,
             mpv_pty: pty,
+            demuxer_child,
             demuxer_pty,
             ipc_bridge,
+            generation: self.generation,
+            socket_path,
         });
         Ok(())
     }
     pub fn stop_music(&mut self) {
-        let Some(inner) = &mut self.inner else { return };
-        inner.mpv_pty.write_all(b"q").unwrap();
-        inner.child.wait().unwrap();
-        self.inner = None;
+        if let Some(inner) = self.inner.take() {
+            self.reapers
+                .push(inner.quit(self.demuxer_exit_send.clone()));
+        }
+    }
+    /// Keep the current mpv instance playing in the background, so it can be crossfaded out
+    /// while a new song is started.
+    pub fn begin_crossfade_out(&mut self) {
+        self.stop_outgoing();
+        self.outgoing = self.inner.take();
+    }
+    /// Stop all mpv instances, wait for them to exit, and remove the IPC sockets
+    pub fn shutdown(&mut self) {
+        self.stop_outgoing();
+        self.stop_music();
+        for reaper in self.reapers.drain(..) {
+            if reaper.join().is_err() {
+                logln!("Mpv reaper thread panicked");
+            }
+        }
+        self.ipc_socket.cleanup();
+    }
+    /// Stop the instance that's being crossfaded out, if any
+    pub fn stop_outgoing(&mut self) {
+        if let Some(outgoing) = self.outgoing.take() {
+            self.reapers
+                .push(outgoing.quit(self.demuxer_exit_send.clone()));
+        }
     }
     pub fn update(&mut self, modal: &mut ModalPopup) {
+        self.update_outgoing();
+        self.reapers.retain(|reaper| !reaper.is_finished());
+        for (generation, status) in self.demuxer_exits.try_iter() {
+            if generation == self.generation {
+                self.demux_term.feed(demuxer_exit_msg(status).as_bytes());
+                self.demux_exit_status = Some(status);
+            }
+        }
         let Some(inner) = &mut self.inner else {
             return;
         };
         if let Err(e) = inner.ipc_bridge.handle_responses() {
             modal.warn("Mpv IPC error", e);
         }
+        self.events.extend(inner.ipc_bridge.event_queue.drain(..));
+        if let Some(time_pos) = inner.ipc_bridge.observed.get::<property::TimePos>() {
+            self.last_time_pos = time_pos;
+        }
+        if let Some(demux_child) = &mut inner.demuxer_child
+            && let Ok(Some(status)) = demux_child.try_wait()
+        {
+            // A crashed demuxer just looks like the end of the stream to mpv
+            if !status.success() {
+                self.abnormal_exit = Some(format!("Demuxer exited with status {status}"));
+            }
+            self.demux_term.feed(demuxer_exit_msg(status).as_bytes());
+            self.demux_exit_status = Some(status);
+            inner.demuxer_child = None;
+        }
         let mut buf = Vec::new();
         let mut demux_buf = Vec::new();
         let mut nbr = NonBlockingReader::from_fd(&inner.mpv_pty).unwrap();
@@ -133,18 +318,11 @@ impl MpvHandler {
                         logln!("Failed to read mpv pty: {e}");
                     }
                     self.mpv_term.feed(&remaining_data);
-                    let stderr = self.mpv_term.contents_to_string();
-                    modal.error(
-                        "Abnormal mpv termination",
-                        format!("Mpv exited with status {status}\nStderr:\n{stderr}"),
-                    );
+                    self.abnormal_exit = Some(format!("Mpv exited with status {status}"));
                 }
             }
             Err(e) => {
-                modal.error(
-                    "Abnormal mpv termination",
-                    format!("Error waiting on mpv: {e}"),
-                );
+                self.abnormal_exit = Some(format!("Error waiting on mpv: {e}"));
             }
         }
         match nbr.read_available(&mut buf) {
@@ -175,6 +353,30 @@ impl MpvHandler {
         }
     }
 
+    fn update_outgoing(&mut self) {
+        let Some(outgoing) = &mut self.outgoing else {
+            return;
+        };
+        outgoing
+            .ipc_bridge
+            .handle_responses()
+            .log_err("Outgoing mpv IPC error");
+        // Discard the output, but keep reading it, so mpv doesn't block on a full pty
+        let mut sink = Vec::new();
+        match NonBlockingReader::from_fd(&outgoing.mpv_pty) {
+            Ok(mut nbr) => {
+                if let Err(e) = nbr.read_available(&mut sink) {
+                    logln!("error reading from outgoing mpv process: {e}");
+                }
+            }
+            Err(e) => logln!("error reading from outgoing mpv process: {e}"),
+        }
+        if !matches!(outgoing.child.try_wait(), Ok(None)) {
+            // Reap the demuxer too
+            self.stop_outgoing();
+        }
+    }
+
     pub fn send_input(&mut self, s: &str) {
         let Some(inner) = &mut self.inner else { return };
         let pty = match self.active_pty_input {
@@ -190,10 +392,16 @@ impl MpvHandler {
 
     pub fn paused(&self) -> bool {
         match &self.inner {
-            Some(inner) => inner.ipc_bridge.observed.paused,
+            Some(inner) => inner.ipc_bridge.paused(),
             None => true,
         }
     }
+    pub fn playback_state(&self) -> PlaybackState {
+        match &self.inner {
+            Some(inner) => inner.ipc_bridge.playback_state(),
+            None => PlaybackState::Stopped,
+        }
+    }
     pub fn mpv_output(&self) -> String {
         let contents = self.mpv_term.contents_to_string();
         let mut out = String::new();
@@ -208,30 +416,127 @@ impl MpvHandler {
     }
     pub fn ab_loop(&self) -> Option<(Option<f64>, Option<f64>)> {
         self.inner.as_ref().map(|inner| {
+            let observed = &inner.ipc_bridge.observed;
             (
-                inner.ipc_bridge.observed.ab_loop_a,
-                inner.ipc_bridge.observed.ab_loop_b,
+                observed.get::<property::AbLoopA>().flatten(),
+                observed.get::<property::AbLoopB>().flatten(),
             )
         })
     }
 
+    /// Title of what's being played, preferring the ICY title of streams
+    pub fn media_title(&self) -> Option<String> {
+        let observed = &self.inner.as_ref()?.ipc_bridge.observed;
+        observed
+            .get::<property::IcyTitle>()
+            .or_else(|| observed.get::<property::MediaTitle>())
+            .filter(|title| !title.is_empty())
+    }
+
+    /// Lyrics embedded in the tags of the current file (USLT, LYRICS, ...).
+    ///
+    /// `None` until mpv has read the tags, `Some(None)` if there are no lyrics.
+    pub fn embedded_lyrics(&self) -> Option<Option<String>> {
+        let metadata = self
+            .inner
+            .as_ref()?
+            .ipc_bridge
+            .observed
+            .get_raw(property::Metadata::NAME)?;
+        // ffmpeg names them differently depending on the format, like `lyrics-eng`
+        let lyrics = metadata.as_object().and_then(|tags| {
+            tags.iter().find_map(|(key, value)| {
+                let key = key.to_ascii_lowercase();
+                let is_lyrics =
+                    key.starts_with("lyrics") || key == "unsyncedlyrics" || key == "uslt";
+                value
+                    .as_str()
+                    .filter(|text| is_lyrics && !text.trim().is_empty())
+                    .map(str::to_owned)
+            })
+        });
+        Some(lyrics)
+    }
+
+    /// The chapters of the current file, and the index of the current one
+    pub fn chapters(&self) -> (Vec<Chapter>, Option<usize>) {
+        let Some(inner) = &self.inner else {
+            return (Vec::new(), None);
+        };
+        let observed = &inner.ipc_bridge.observed;
+        let chapters = observed.get::<property::ChapterList>().unwrap_or_default();
+        let current = observed
+            .get::<property::CurrentChapter>()
+            .and_then(|idx| usize::try_from(idx).ok())
+            .filter(|&idx| idx < chapters.len());
+        (chapters, current)
+    }
+
     pub(crate) fn time_info(&self) -> Option<TimeInfo> {
-        self.inner.as_ref().map(|inner| TimeInfo {
-            pos: inner.ipc_bridge.observed.time_pos,
-            duration: inner.ipc_bridge.observed.duration,
+        self.inner.as_ref().map(|inner| {
+            let observed = &inner.ipc_bridge.observed;
+            TimeInfo {
+                pos: observed.get::<property::TimePos>().unwrap_or_default(),
+                duration: observed.get::<property::Duration>().unwrap_or_default(),
+            }
         })
     }
 
-    pub(crate) fn poll_event(&mut self) -> Option<IpcEvent> {
-        match &mut self.inner {
-            Some(inner) => inner.ipc_bridge.event_queue.pop_front(),
-            None => None,
+    /// Start observing a property, for as long as it's needed.
+    ///
+    /// The observation persists across songs until a matching [`Self::unobserve`] call.
+    pub fn observe(&mut self, name: &str) {
+        let count = self.extra_observed.entry(name.to_owned()).or_default();
+        *count += 1;
+        if *count == 1
+            && let Some(inner) = &mut self.inner
+        {
+            inner
+                .ipc_bridge
+                .observe_property_by_name(name)
+                .log_err("Failed to observe property");
         }
     }
+    /// Stop observing a property previously observed with [`Self::observe`]
+    pub fn unobserve(&mut self, name: &str) {
+        let Some(count) = self.extra_observed.get_mut(name) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.extra_observed.remove(name);
+            if let Some(inner) = &mut self.inner {
+                inner
+                    .ipc_bridge
+                    .unobserve_property_by_name(name)
+                    .log_err("Failed to unobserve property");
+            }
+        }
+    }
+
+    pub(crate) fn poll_event(&mut self) -> Option<IpcEvent> {
+        self.events.pop_front()
+    }
+
+    /// The last playback position reported by mpv. Still available after mpv exits.
+    pub(crate) fn last_time_pos(&self) -> f64 {
+        self.last_time_pos
+    }
+
+    /// Take the description of the last abnormal mpv termination, if there was one
+    pub(crate) fn take_abnormal_exit(&mut self) -> Option<String> {
+        self.abnormal_exit.take()
+    }
 
     pub(crate) fn demuxer_active(&self) -> bool {
         self.read_demuxer
     }
+    /// Send a command to the IPC bridge of the instance being crossfaded out, if it exists
+    pub(crate) fn outgoing_ipc<T>(&mut self, fun: impl FnOnce(&mut ipc::Bridge) -> T) -> Option<T> {
+        self.outgoing
+            .as_mut()
+            .map(|outgoing| fun(&mut outgoing.ipc_bridge))
+    }
     /// Send a command to the IPC bridge, if it exists
     pub(crate) fn ipc<'br, T, F>(&'br mut self, fun: F) -> Option<T>
     where
@@ -244,20 +549,62 @@ impl MpvHandler {
     }
 }
 
+/// Wait for mpv to bring up its IPC server, and connect to it
+fn connect_ipc(
+    child: &mut Child,
+    pty: &mut Pty,
+    socket_path: &Path,
+) -> anyhow::Result<ipc::Bridge> {
+    let attempts = 5;
+    for i in 0..attempts {
+        std::thread::sleep(Duration::from_millis(100));
+        match ipc::Bridge::connect(socket_path) {
+            Ok(bridge) => return Ok(bridge),
+            Err(e) => {
+                if let Some(status) = child.try_wait()? {
+                    let mut stderr = Vec::new();
+                    let result = pty.read_to_end(&mut stderr);
+                    if let Err(e) = result {
+                        logln!("Failed to read mpv pty: {e}");
+                    }
+                    let mut term = Term::new(80);
+                    term.feed(&stderr);
+                    let stderr = term.contents_to_string();
+                    anyhow::bail!("mpv exited with {status}.\nStderr:\n{stderr}");
+                }
+                logln!("mpv connection attempt #{i}: {e}");
+            }
+        }
+    }
+    anyhow::bail!("Failed connect to mpv");
+}
+
 pub struct TimeInfo {
     pub pos: f64,
     pub duration: f64,
 }
 
-impl Default for MpvHandler {
-    fn default() -> Self {
+impl MpvHandler {
+    pub fn new(ipc_socket: ipc::SocketPath) -> Self {
+        let (demuxer_exit_send, demuxer_exits) = channel();
         Self {
             mpv_term: Term::new(80),
             demux_term: Term::new(80),
             demux_cmd_name: String::new(),
+            demux_exit_status: None,
             inner: None,
+            outgoing: None,
             read_demuxer: true,
             active_pty_input: ActivePtyInput::Mpv,
+            events: VecDeque::new(),
+            abnormal_exit: None,
+            last_time_pos: 0.0,
+            ipc_socket,
+            extra_observed: HashMap::new(),
+            generation: 0,
+            reapers: Vec::new(),
+            demuxer_exit_send,
+            demuxer_exits,
         }
     }
 }
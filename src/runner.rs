@@ -2,7 +2,8 @@
 
 use {
     crate::{
-        app::{self, App, tray::EventFlags},
+        app::{self, App, cover_art::CoverTexture, tray::EventFlags},
+        ipc::PlaybackState,
         rect_math::{Rect, Vec2, rect_ensure_within},
         util::result_ext::ResultModalExt as _,
     },
@@ -21,6 +22,7 @@ use {
 struct CtxMenuWin {
     rw: FBox<RenderWindow>,
     sf_egui: SfEgui,
+    cover: CoverTexture,
 }
 
 pub fn run(
@@ -71,7 +73,7 @@ pub fn run(
                                         .core
                                         .playlist
                                         .iter()
-                                        .position(|item| item.path == stripped)
+                                        .position(|item| item.path() == Some(stripped))
                                     {
                                         app.focus_and_play(pos);
                                     }
@@ -109,7 +111,7 @@ pub fn run(
             } else {
                 let desired = Rect {
                     pos: Vec2 { x, y },
-                    size: Vec2 { x: 200, y: 100 },
+                    size: Vec2 { x: 200, y: 120 },
                 };
                 let desk_size = VideoMode::desktop_mode();
                 let desk_rect = Rect {
@@ -142,7 +144,11 @@ pub fn run(
                 }
                 app.ui
                     .apply_colorix_theme(app.core.cfg.theme.as_ref(), sf_egui.context());
-                tray_popup_win = Some(CtxMenuWin { rw, sf_egui });
+                tray_popup_win = Some(CtxMenuWin {
+                    rw,
+                    sf_egui,
+                    cover: CoverTexture::default(),
+                });
             }
         }
         app.update_tooltip();
@@ -217,7 +223,9 @@ pub fn run(
                 }
             } else {
                 app.bg_update();
-                std::thread::sleep(Duration::from_millis(250));
+                // Fades need finer granularity to sound smooth
+                let sleep_ms = if app.core.fading() { 16 } else { 250 };
+                std::thread::sleep(Duration::from_millis(sleep_ms));
             }
         }
         if app.ui.quit_requested {
@@ -225,6 +233,9 @@ pub fn run(
         }
     }
     app.save();
+    // Don't leave any child processes (or sockets) behind
+    app.core.mpv_handler.shutdown();
+    app.core.extractor.cleanup();
 }
 
 fn skip_taskbar_for_window_x11(rw: &RenderWindow) -> anyhow::Result<()> {
@@ -309,13 +320,24 @@ fn update_tray_window(win: &mut CtxMenuWin, app: &mut App) -> Option<TrayUpdateM
                     .err_popup("Volume set error", &mut app.modal);
             }
         });
-        let play_pause_label = if app.paused_or_stopped() {
-            "▶"
-        } else {
-            "⏸"
-        };
-        if let Some(name) = app.currently_playing_name() {
-            ui.add(egui::Label::new(name).wrap_mode(egui::TextWrapMode::Extend));
+        let state = app.core.mpv_handler.playback_state();
+        let play_pause_label = if state.is_playing() { "⏸" } else { "▶" };
+        ui.horizontal(|ui| {
+            let song = app.core.selected_song_path();
+            if let Some(texture) = win.cover.get(ui.ctx(), &app.core.covers, song.as_deref()) {
+                ui.add(egui::Image::new(texture).max_size(egui::vec2(48.0, 48.0)));
+            }
+            ui.vertical(|ui| {
+                if let Some(name) = app.currently_playing_name() {
+                    ui.add(egui::Label::new(name).wrap_mode(egui::TextWrapMode::Extend));
+                }
+                if let Some(title) = app.core.stream_title() {
+                    ui.add(egui::Label::new(title).wrap_mode(egui::TextWrapMode::Extend));
+                }
+            });
+        });
+        if matches!(state, PlaybackState::Buffering | PlaybackState::Seeking) {
+            ui.label(state.label());
         }
         ui.add_space(4.0);
         ui.horizontal(|ui| {
@@ -327,7 +349,7 @@ fn update_tray_window(win: &mut CtxMenuWin, app: &mut App) -> Option<TrayUpdateM
                 app.core.play_or_toggle_pause(&mut app.modal);
             }
             if ui.button("⏹").clicked() {
-                app.core.stop_music();
+                app.core.stop_music(&mut app.modal);
             }
             if ui.button(app::ui::ICO_NEXT).clicked() {
                 app.core.play_next(&mut app.modal);
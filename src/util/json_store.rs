@@ -0,0 +1,80 @@
+//! JSON files kept in the data or cache directory of the app
+
+use {
+    crate::{logln, util::result_ext::LogErrExt as _},
+    directories::ProjectDirs,
+    serde::{Serialize, de::DeserializeOwned},
+    std::path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy)]
+pub enum Dir {
+    /// For things the user would miss, like bookmarks
+    Data,
+    /// For things that can be recomputed
+    Cache,
+}
+
+/// Path of `file_name` in one of the app's directories
+pub fn path(dir: Dir, file_name: &str) -> Option<PathBuf> {
+    let dirs = ProjectDirs::from("", "crumblingstatue", "mpvfrog")?;
+    let dir = match dir {
+        Dir::Data => dirs.data_dir(),
+        Dir::Cache => dirs.cache_dir(),
+    };
+    Some(dir.join(file_name))
+}
+
+/// Load a JSON file. Falls back to the default if it doesn't exist or can't be loaded.
+///
+/// A file that can't be parsed is moved aside (to `*.bak`), so saving the default doesn't
+/// overwrite what might still be recovered by hand.
+///
+/// `what` describes the contents, for the error message.
+pub fn load<T: DeserializeOwned + Default>(dir: Dir, file_name: &str, what: &str) -> T {
+    let Some(path) = path(dir, file_name).filter(|path| path.exists()) else {
+        return T::default();
+    };
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            logln!("Failed to load {what}: {e}");
+            return T::default();
+        }
+    };
+    match serde_json::from_slice(&data) {
+        Ok(value) => value,
+        Err(e) => {
+            let backup = with_suffix(&path, ".bak");
+            logln!(
+                "Failed to load {what}: {e}. Moving it aside to {}",
+                backup.display()
+            );
+            std::fs::rename(&path, &backup).log_err("Failed to move it aside");
+            T::default()
+        }
+    }
+}
+
+/// Save a JSON file. It's written to a temporary file first, so a crash can't leave a
+/// half-written file behind.
+pub fn save<T: Serialize>(dir: Dir, file_name: &str, what: &str, value: &T) {
+    let Some(path) = path(dir, file_name) else {
+        return;
+    };
+    let tmp_path = with_suffix(&path, ".tmp");
+    let result = std::fs::create_dir_all(path.parent().unwrap())
+        .map_err(anyhow::Error::from)
+        .and_then(|()| Ok(std::fs::write(&tmp_path, serde_json::to_vec(value)?)?))
+        .and_then(|()| Ok(std::fs::rename(&tmp_path, &path)?));
+    if let Err(e) = result {
+        logln!("Failed to save {what}: {e}");
+    }
+}
+
+/// `path` with `suffix` appended to the file name, like `loops.json.bak`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
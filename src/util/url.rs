@@ -0,0 +1,16 @@
+//! Turning paths into URLs
+
+use std::path::Path;
+
+/// Percent-encode a path for use in a URL, keeping the slashes
+pub fn encode_path(path: &Path) -> String {
+    let mut url = String::new();
+    for byte in path.as_os_str().as_encoded_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(byte) {
+            url.push(char::from(*byte));
+        } else {
+            url.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    url
+}
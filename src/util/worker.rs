@@ -0,0 +1,70 @@
+//! Running jobs in a background thread, so the ui doesn't wait for them
+
+use std::sync::{
+    Arc,
+    mpsc::{Receiver, Sender, channel},
+};
+
+type Work<Job, Output> = Arc<dyn Fn(&Job) -> Output + Send + Sync>;
+
+/// Does jobs one after another in a background thread.
+///
+/// The thread is started with the first job, and started again if it went away
+/// (e.g. because a job panicked).
+pub struct Worker<Job, Output> {
+    /// What the worker does, for logging
+    name: &'static str,
+    work: Work<Job, Output>,
+    thread: Option<Channels<Job, Output>>,
+}
+
+struct Channels<Job, Output> {
+    jobs: Sender<Job>,
+    results: Receiver<(Job, Output)>,
+}
+
+impl<Job: Send + 'static, Output: Send + 'static> Worker<Job, Output> {
+    pub fn new(name: &'static str, work: impl Fn(&Job) -> Output + Send + Sync + 'static) -> Self {
+        Self {
+            name,
+            work: Arc::new(work),
+            thread: None,
+        }
+    }
+    /// Queue a job. Returns whether it was queued.
+    pub fn send(&mut self, job: Job) -> bool {
+        let work = &self.work;
+        let channels = self.thread.get_or_insert_with(|| spawn(work.clone()));
+        if channels.jobs.send(job).is_err() {
+            crate::logln!("{} worker went away", self.name);
+            self.thread = None;
+            return false;
+        }
+        true
+    }
+    /// The jobs that are done since the last call, with their output
+    pub fn results(&self) -> impl Iterator<Item = (Job, Output)> + '_ {
+        self.thread
+            .iter()
+            .flat_map(|channels| channels.results.try_iter())
+    }
+}
+
+fn spawn<Job: Send + 'static, Output: Send + 'static>(
+    work: Work<Job, Output>,
+) -> Channels<Job, Output> {
+    let (job_send, job_recv) = channel::<Job>();
+    let (result_send, result_recv) = channel();
+    std::thread::spawn(move || {
+        for job in job_recv {
+            let output = work(&job);
+            if result_send.send((job, output)).is_err() {
+                break;
+            }
+        }
+    });
+    Channels {
+        jobs: job_send,
+        results: result_recv,
+    }
+}